@@ -1,60 +1,258 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
 
 use ractor::{Actor, ActorProcessingErr, ActorRef};
-use reader_core::http::http_get_bytes;
-use reader_core::image::{encode_avif, ImageError};
+use reader_core::cache::{self, CacheKind};
+use reader_core::config::CONFIG;
+use reader_core::http::Fetcher;
+use reader_core::image::{encode_avif, ImageError, ImageTicket};
 
 use crate::message::ImageMsg;
 
-/// ractor actor that owns the image re-encode path. On each message it
-/// spawns a dedicated OS thread: both `http_get_bytes` (blocking reqwest)
-/// and `encode_avif` (ravif + rayon) are CPU/IO heavy and benefit from
-/// running outside the tokio executor.
+/// One queued fetch + encode.
+struct Job {
+    url: String,
+    cache_path: PathBuf,
+    fetcher: Arc<dyn Fetcher>,
+}
+
+/// ractor actor that owns the image re-encode path. Work is handed to a
+/// fixed pool of `CONFIG.image_workers` OS threads through a queue of at
+/// most `CONFIG.image_queue_size` jobs: both the fetch (blocking, on a
+/// per-thread runtime for the network fetcher) and `encode_avif` (ravif +
+/// rayon) are CPU/IO heavy and benefit from running outside the tokio executor, but one thread per
+/// image pins every core on an image-heavy article.
 pub struct ImageActor;
 
+pub struct ImageState {
+    jobs: SyncSender<Job>,
+    /// Done-senders of every ticket handed out for a URL that is queued
+    /// or encoding, keyed by URL.
+    in_flight: HashMap<String, Vec<SyncSender<()>>>,
+}
+
 impl Actor for ImageActor {
     type Msg = ImageMsg;
-    type State = ();
+    type State = ImageState;
     type Arguments = ();
 
     async fn pre_start(
         &self,
-        _myself: ActorRef<ImageMsg>,
+        myself: ActorRef<ImageMsg>,
         _args: (),
-    ) -> std::result::Result<(), ActorProcessingErr> {
-        Ok(())
+    ) -> std::result::Result<ImageState, ActorProcessingErr> {
+        let (jobs, queue) = mpsc::sync_channel(CONFIG.image_queue_size);
+        let queue = Arc::new(Mutex::new(queue));
+        for index in 0..CONFIG.image_workers.max(1) {
+            let queue = Arc::clone(&queue);
+            let myself = myself.clone();
+            std::thread::Builder::new()
+                .name(format!("image-worker-{}", index))
+                .spawn(move || {
+                    work(&queue, |url| {
+                        let _ = myself.cast(ImageMsg::Finished { url });
+                    })
+                })?;
+        }
+        Ok(ImageState {
+            jobs,
+            in_flight: HashMap::new(),
+        })
     }
 
     async fn handle(
         &self,
         _myself: ActorRef<ImageMsg>,
         msg: ImageMsg,
-        _state: &mut (),
+        state: &mut ImageState,
     ) -> std::result::Result<(), ActorProcessingErr> {
-        let ImageMsg::Encode {
-            url,
-            cache_path,
-            done,
-        } = msg;
-        std::thread::spawn(move || {
-            if let Err(e) = fetch_encode_write(&url, &cache_path) {
-                eprintln!("image worker {}: {}", url, e);
+        match msg {
+            ImageMsg::Encode {
+                url,
+                cache_path,
+                fetcher,
+                reply,
+            } => {
+                let _ = reply.send(state.enqueue(url, cache_path, fetcher));
+            }
+            ImageMsg::Finished { url } => {
+                for done in state.in_flight.remove(&url).unwrap_or_default() {
+                    let _ = done.try_send(());
+                }
             }
-            let _ = done.send(());
-        });
+        }
         Ok(())
     }
 }
 
+impl ImageState {
+    /// Hand out a ticket for `url`, joining its in-flight job if there is
+    /// one and queueing a new job otherwise. Declines when the queue is
+    /// full so the caller falls back to the original image URL.
+    fn enqueue(
+        &mut self,
+        url: String,
+        cache_path: PathBuf,
+        fetcher: Arc<dyn Fetcher>,
+    ) -> Option<ImageTicket> {
+        let (done, ticket) = mpsc::sync_channel(1);
+        if let Some(waiters) = self.in_flight.get_mut(&url) {
+            waiters.push(done);
+            return Some(ImageTicket { done: ticket });
+        }
+        let job = Job {
+            url: url.clone(),
+            cache_path,
+            fetcher,
+        };
+        match self.jobs.try_send(job) {
+            Ok(()) => {
+                self.in_flight.insert(url, vec![done]);
+                Some(ImageTicket { done: ticket })
+            }
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => None,
+        }
+    }
+}
+
+/// Worker loop: pull jobs until the actor (and with it the queue's
+/// sender) goes away, reporting each finished URL through `finished`. A
+/// job that panics (a decoder tripping over a malformed image) is
+/// reported like one that failed, and the worker carries on.
+fn work(queue: &Mutex<Receiver<Job>>, finished: impl Fn(String)) {
+    loop {
+        let job = match queue.lock() {
+            Ok(queue) => queue.recv(),
+            Err(_) => return,
+        };
+        let Ok(Job {
+            url,
+            cache_path,
+            fetcher,
+        }) = job
+        else {
+            return;
+        };
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            fetch_encode_write(fetcher.as_ref(), &url, &cache_path)
+        }));
+        match outcome {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("image worker {}: {}", url, e),
+            Err(_) => eprintln!("image worker {}: panicked", url),
+        }
+        finished(url);
+    }
+}
+
 /// Pure linear pipeline: fetch, encode, write. Using `?` keeps nesting
 /// flat — the old version had `match` inside `match` inside `if let` at
 /// four levels deep.
-fn fetch_encode_write(url: &str, cache_path: &Path) -> Result<(), ImageError> {
-    let bytes = http_get_bytes(url)?;
+fn fetch_encode_write(
+    fetcher: &dyn Fetcher,
+    url: &str,
+    cache_path: &Path,
+) -> Result<(), ImageError> {
+    let bytes = fetcher.get_bytes(url)?;
     let avif = encode_avif(&bytes)?;
     if let Some(parent) = cache_path.parent() {
         std::fs::create_dir_all(parent)?;
     }
     std::fs::write(cache_path, &avif)?;
+    if let Err(e) = cache::record_entry(
+        CacheKind::Image,
+        &cache_path.to_string_lossy(),
+        avif.len() as u64,
+    ) {
+        eprintln!("image cache record {}: {}", cache_path.display(), e);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use reader_core::http::{FetchSession, FixtureFetcher};
+    use reader_core::HttpError;
+
+    use super::*;
+
+    fn fetcher() -> Arc<dyn Fetcher> {
+        Arc::new(FixtureFetcher::default())
+    }
+
+    fn state(queue_size: usize) -> (ImageState, Receiver<Job>) {
+        let (jobs, queue) = mpsc::sync_channel(queue_size);
+        let state = ImageState {
+            jobs,
+            in_flight: HashMap::new(),
+        };
+        (state, queue)
+    }
+
+    #[test]
+    fn same_url_shares_one_job_and_every_ticket_is_signalled() {
+        let (mut state, queue) = state(4);
+        let first = state
+            .enqueue("a.png".into(), "a.avif".into(), fetcher())
+            .unwrap();
+        let second = state
+            .enqueue("a.png".into(), "a.avif".into(), fetcher())
+            .unwrap();
+        assert_eq!(queue.try_iter().count(), 1);
+        for done in state.in_flight.remove("a.png").unwrap() {
+            done.try_send(()).unwrap();
+        }
+        assert!(first.done.try_recv().is_ok());
+        assert!(second.done.try_recv().is_ok());
+    }
+
+    #[test]
+    fn full_queue_declines() {
+        let (mut state, _queue) = state(1);
+        assert!(state
+            .enqueue("a.png".into(), "a.avif".into(), fetcher())
+            .is_some());
+        assert!(state
+            .enqueue("b.png".into(), "b.avif".into(), fetcher())
+            .is_none());
+        assert!(!state.in_flight.contains_key("b.png"));
+    }
+
+    struct Panicking;
+
+    impl Fetcher for Panicking {
+        fn session(&self) -> Result<Box<dyn FetchSession>, HttpError> {
+            panic!("decoder bug")
+        }
+
+        fn get_bytes(&self, _url: &str) -> Result<Vec<u8>, HttpError> {
+            panic!("decoder bug")
+        }
+    }
+
+    #[test]
+    fn a_panicking_job_is_finished_and_the_worker_carries_on() {
+        let (jobs, queue) = mpsc::sync_channel(2);
+        for (url, fetcher) in [
+            ("bad.png", Arc::new(Panicking) as Arc<dyn Fetcher>),
+            ("next.png", fetcher()),
+        ] {
+            jobs.try_send(Job {
+                url: url.into(),
+                cache_path: PathBuf::new(),
+                fetcher,
+            })
+            .unwrap();
+        }
+        drop(jobs);
+        let (finished, reported) = mpsc::channel();
+        work(&Mutex::new(queue), |url| finished.send(url).unwrap());
+        assert_eq!(
+            reported.try_iter().collect::<Vec<_>>(),
+            ["bad.png", "next.png"]
+        );
+    }
+}
@@ -17,7 +17,7 @@ use std::sync::mpsc;
 use once_cell::sync::OnceCell;
 use ractor::concurrency::JoinHandle;
 use ractor::{Actor, ActorRef};
-use reader_core::image::{register_encoder, EncoderFn, ImageTicket};
+use reader_core::image::{register_encoder, EncoderFn};
 
 use actor::ImageActor;
 
@@ -33,17 +33,20 @@ pub async fn boot() -> Result<(), ImageActorError> {
             .map_err(|e| ImageActorError::SpawnFailed(e.to_string()))?;
     let _ = ACTOR_HANDLE.set(handle);
 
-    let encoder: EncoderFn = Box::new(move |url, cache_path| {
-        let (tx, rx) = mpsc::sync_channel(1);
+    // The encoder is only invoked from the blocking render path, so it
+    // can wait for the actor's answer synchronously.
+    let encoder: EncoderFn = Box::new(move |url, cache_path, fetcher| {
+        let (reply, answer) = mpsc::sync_channel(1);
         if let Err(e) = actor_ref.cast(ImageMsg::Encode {
             url,
             cache_path,
-            done: tx,
+            fetcher,
+            reply,
         }) {
             eprintln!("ImageActor cast failed: {}", e);
             return None;
         }
-        Some(ImageTicket { done: rx })
+        answer.recv().ok().flatten()
     });
     register_encoder(encoder);
     Ok(())
@@ -1,14 +1,24 @@
 use std::path::PathBuf;
 use std::sync::mpsc::SyncSender;
+use std::sync::Arc;
+
+use reader_core::http::Fetcher;
+use reader_core::image::ImageTicket;
 
 /// Request messages accepted by the [`super::actor::ImageActor`].
 pub enum ImageMsg {
-    /// Fetch `url`, re-encode it to AVIF, and write the result to
-    /// `cache_path`. `done` is signalled when the worker has finished
-    /// (success or failure) so the caller can bound its wait.
+    /// Fetch `url` through `fetcher`, re-encode it to AVIF, and write the
+    /// result to `cache_path`. The actor answers on `reply` with a ticket that is
+    /// signalled when the encode has finished (success or failure), or
+    /// `None` if the work queue is full and the request was declined.
+    /// A URL that is already queued or encoding shares the existing job.
     Encode {
         url: String,
         cache_path: PathBuf,
-        done: SyncSender<()>,
+        fetcher: Arc<dyn Fetcher>,
+        reply: SyncSender<Option<ImageTicket>>,
     },
+    /// Sent by a pool worker once it is done with `url`, so the actor can
+    /// signal every ticket waiting on it.
+    Finished { url: String },
 }
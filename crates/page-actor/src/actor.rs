@@ -1,45 +1,172 @@
-use ractor::{Actor, ActorProcessingErr, ActorRef};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ractor::{Actor, ActorProcessingErr, ActorRef, RpcReplyPort};
+use reader_core::http::Fetcher;
 use reader_core::pipeline;
+use reader_core::pipeline_error::PipelineError;
 
-use crate::message::PageMsg;
+use crate::message::{PageMsg, PageResult, RenderKey};
 
-/// ractor actor wrapping [`reader_core::pipeline::render`]. Each render
+/// ractor actor wrapping [`reader_core::pipeline::render`] and
+/// [`reader_core::pipeline::render_html`]. Each render
 /// message is dispatched to its own tokio task so concurrent renders
 /// don't serialize on the mailbox.
+///
+/// URL renders are single-flight: while a render for a given URL, mode
+/// and set of fetch options is running, later identical
+/// requests subscribe to its result instead of fetching the page again.
+/// Every render downloads through the fetcher the actor was started with.
 pub struct PageActor;
 
+pub struct PageState {
+    fetcher: Arc<dyn Fetcher>,
+    /// Reply ports waiting on each running URL render, leader included.
+    in_flight: HashMap<RenderKey, Vec<RpcReplyPort<PageResult>>>,
+}
+
 impl Actor for PageActor {
     type Msg = PageMsg;
-    type State = ();
-    type Arguments = ();
+    type State = PageState;
+    type Arguments = Arc<dyn Fetcher>;
 
     async fn pre_start(
         &self,
         _myself: ActorRef<PageMsg>,
-        _args: (),
-    ) -> std::result::Result<(), ActorProcessingErr> {
-        Ok(())
+        fetcher: Arc<dyn Fetcher>,
+    ) -> std::result::Result<PageState, ActorProcessingErr> {
+        Ok(PageState {
+            fetcher,
+            in_flight: HashMap::new(),
+        })
     }
 
     async fn handle(
         &self,
-        _myself: ActorRef<PageMsg>,
+        myself: ActorRef<PageMsg>,
         msg: PageMsg,
-        _state: &mut (),
+        state: &mut PageState,
     ) -> std::result::Result<(), ActorProcessingErr> {
-        let PageMsg::Render {
-            url,
-            min_id,
-            mode,
-            reply,
-        } = msg;
         // Fan out: each render runs on its own tokio task so the mailbox
         // drains fast and concurrent renders don't serialize behind each
         // other.
-        tokio::spawn(async move {
-            let result = pipeline::render(&url, &min_id, mode).await;
-            let _ = reply.send(result);
-        });
+        match msg {
+            PageMsg::Render {
+                url,
+                min_id,
+                mode,
+                fetch,
+                reply,
+            } => {
+                let key = (url, mode, fetch);
+                if !state.subscribe(key.clone(), reply) {
+                    return Ok(());
+                }
+                let fetcher = Arc::clone(&state.fetcher);
+                let (url, mode, fetch) = key.clone();
+                let render = tokio::spawn(async move {
+                    pipeline::render(&fetcher, &url, &min_id, mode, &fetch).await
+                });
+                // Always report back, even if the render panicked, so its
+                // waiters are answered and the key is freed.
+                tokio::spawn(async move {
+                    let result = render
+                        .await
+                        .unwrap_or_else(|e| Err(PipelineError::RenderAborted(e.to_string())))
+                        .map_err(Arc::new);
+                    let _ = myself.cast(PageMsg::Rendered { key, result });
+                });
+            }
+            PageMsg::RenderHtml {
+                url,
+                html,
+                min_id,
+                mode,
+                reply,
+            } => {
+                let fetcher = Arc::clone(&state.fetcher);
+                tokio::spawn(async move {
+                    let result = pipeline::render_html(&fetcher, &url, html, &min_id, mode)
+                        .await
+                        .map_err(Arc::new);
+                    let _ = reply.send(result);
+                });
+            }
+            PageMsg::Rendered { key, result } => {
+                for reply in state.in_flight.remove(&key).unwrap_or_default() {
+                    let _ = reply.send(result.clone());
+                }
+            }
+        }
         Ok(())
     }
 }
+
+impl PageState {
+    /// Register `reply` as waiting on `key`. Returns `true` if it is the
+    /// first waiter, i.e. the caller must start the render.
+    fn subscribe(&mut self, key: RenderKey, reply: RpcReplyPort<PageResult>) -> bool {
+        let waiters = self.in_flight.entry(key).or_default();
+        waiters.push(reply);
+        waiters.len() == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ractor::rpc::CallResult;
+    use reader_core::http::{FetchOptions, FetchSession};
+    use reader_core::http_error::HttpError;
+    use reader_core::render_mode::RenderMode;
+
+    use super::*;
+
+    /// A fetcher whose renders panic, as a bug in the fetch path would.
+    struct Panicking;
+
+    impl Fetcher for Panicking {
+        fn session(&self) -> Result<Box<dyn FetchSession>, HttpError> {
+            panic!("fetch path bug")
+        }
+
+        fn get_bytes(&self, _url: &str) -> Result<Vec<u8>, HttpError> {
+            panic!("fetch path bug")
+        }
+    }
+
+    #[test]
+    fn a_panicking_render_still_answers_and_frees_its_key() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let fetcher: Arc<dyn Fetcher> = Arc::new(Panicking);
+            let (actor, _) = Actor::spawn(None, PageActor, fetcher).await.unwrap();
+            // The second identical request would join a leaked in-flight
+            // entry and never be answered.
+            for _ in 0..2 {
+                let result = actor
+                    .call(
+                        |reply| PageMsg::Render {
+                            url: "https://example.com/post".into(),
+                            min_id: String::new(),
+                            mode: RenderMode::View,
+                            fetch: FetchOptions::default(),
+                            reply,
+                        },
+                        Some(Duration::from_secs(5)),
+                    )
+                    .await
+                    .unwrap();
+                assert!(matches!(
+                    result,
+                    CallResult::Success(Err(e)) if matches!(*e, PipelineError::RenderAborted(_))
+                ));
+            }
+            actor.stop(None);
+        });
+    }
+}
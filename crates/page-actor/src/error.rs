@@ -1,9 +1,12 @@
+use std::sync::Arc;
+
 use reader_core::pipeline_error::PipelineError;
 
 /// Errors returned by [`super::render_page`] and [`super::boot`].
 ///
 /// `Pipeline` wraps the narrow error returned by
-/// [`reader_core::pipeline::render`]; the other variants cover actor-system
+/// [`reader_core::pipeline::render`], shared between every caller that
+/// waited on the same render; the other variants cover actor-system
 /// failures (not booted, call dropped, timeout) that are specific to the
 /// ractor wrapping.
 #[derive(Debug, thiserror::Error)]
@@ -24,5 +27,5 @@ pub enum PageActorError {
     Timeout,
 
     #[error(transparent)]
-    Pipeline(#[from] PipelineError),
+    Pipeline(#[from] Arc<PipelineError>),
 }
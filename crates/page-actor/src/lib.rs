@@ -1,20 +1,27 @@
 //! Page-rendering actor. See [`actor::PageActor`] for the work loop and
 //! [`message::PageMsg`] for the message protocol.
 //!
-//! The public API of this crate is `boot()` + `render_page()`: the
-//! server should never touch the underlying ractor types directly.
+//! The public API of this crate is `boot()` + `render_page()` /
+//! `render_html_page()`: the server should never touch the underlying
+//! ractor types directly.
 
 mod actor;
 mod error;
 mod message;
 
 pub use error::PageActorError;
-pub use message::PageMsg;
+pub use message::{PageMsg, PageResult, RenderKey, RenderResult};
+
+use std::sync::Arc;
+use std::time::Duration;
 
 use once_cell::sync::OnceCell;
 use ractor::concurrency::JoinHandle;
 use ractor::rpc::CallResult;
-use ractor::{Actor, ActorRef};
+use ractor::{Actor, ActorRef, RpcReplyPort};
+use reader_core::config::CONFIG;
+use reader_core::http::{FetchOptions, Fetcher};
+use reader_core::pipeline::RenderedPage;
 use reader_core::render_mode::RenderMode;
 
 use actor::PageActor;
@@ -23,11 +30,11 @@ static PAGE_REF: OnceCell<ActorRef<PageMsg>> = OnceCell::new();
 static ACTOR_HANDLE: OnceCell<JoinHandle<()>> = OnceCell::new();
 
 /// Spawn the page actor and stash the actor ref for later `render_page`
-/// calls. Must be invoked from inside a tokio runtime, exactly once per
-/// process.
-pub async fn boot() -> Result<(), PageActorError> {
+/// calls. Every render downloads through `fetcher`. Must be invoked from
+/// inside a tokio runtime, exactly once per process.
+pub async fn boot(fetcher: Arc<dyn Fetcher>) -> Result<(), PageActorError> {
     let (actor_ref, handle): (ActorRef<PageMsg>, JoinHandle<()>) =
-        Actor::spawn(Some("page".into()), PageActor, ())
+        Actor::spawn(Some("page".into()), PageActor, fetcher)
             .await
             .map_err(|e| PageActorError::SpawnFailed(e.to_string()))?;
     PAGE_REF
@@ -37,25 +44,52 @@ pub async fn boot() -> Result<(), PageActorError> {
     Ok(())
 }
 
-/// Ask the page actor to render a URL.
+/// Ask the page actor to render a URL. With `fetch.revalidate`,
+/// `Ok(None)` means the origin reports the page unchanged since those
+/// validators.
 pub async fn render_page(
     url: &str,
     min_id: &str,
     mode: RenderMode,
+    fetch: FetchOptions,
+) -> Result<Option<RenderedPage>, PageActorError> {
+    let url = url.to_owned();
+    let min_id = min_id.to_owned();
+    call_actor(|reply| PageMsg::Render {
+        url,
+        min_id,
+        mode,
+        fetch,
+        reply,
+    })
+    .await
+}
+
+/// Ask the page actor to render HTML submitted by the caller for `url`.
+pub async fn render_html_page(
+    url: &str,
+    html: String,
+    min_id: &str,
+    mode: RenderMode,
 ) -> Result<String, PageActorError> {
-    let actor = PAGE_REF.get().ok_or(PageActorError::NotBooted)?;
     let url = url.to_owned();
     let min_id = min_id.to_owned();
+    call_actor(|reply| PageMsg::RenderHtml {
+        url,
+        html,
+        min_id,
+        mode,
+        reply,
+    })
+    .await
+}
+
+async fn call_actor<T: Send + 'static>(
+    build: impl FnOnce(RpcReplyPort<RenderResult<T>>) -> PageMsg,
+) -> Result<T, PageActorError> {
+    let actor = PAGE_REF.get().ok_or(PageActorError::NotBooted)?;
     let call = actor
-        .call(
-            |reply| PageMsg::Render {
-                url,
-                min_id,
-                mode,
-                reply,
-            },
-            None,
-        )
+        .call(build, Some(Duration::from_secs(CONFIG.render_timeout)))
         .await
         .map_err(|e| PageActorError::CallFailed(e.to_string()))?;
     match call {
@@ -1,15 +1,45 @@
+use std::sync::Arc;
+
 use ractor::RpcReplyPort;
+use reader_core::http::FetchOptions;
+use reader_core::pipeline::RenderedPage;
 use reader_core::pipeline_error::PipelineError;
 use reader_core::render_mode::RenderMode;
 
+/// Outcome of a render as seen by callers. The error is shared because
+/// one failed render may be reported to several waiting callers.
+pub type RenderResult<T> = Result<T, Arc<PipelineError>>;
+
+/// Reply to [`PageMsg::Render`]: the fresh render, or `None` if the
+/// origin reported the page unchanged since `fetch.revalidate`.
+pub type PageResult = RenderResult<Option<RenderedPage>>;
+
+/// What makes two [`PageMsg::Render`] requests interchangeable.
+pub type RenderKey = (String, RenderMode, FetchOptions);
+
 /// Request messages accepted by the [`super::actor::PageActor`].
 pub enum PageMsg {
-    /// Fetch a URL and render it through the reader pipeline. The reply
-    /// port carries the rendered HTML (or the pipeline error).
+    /// Fetch a URL and render it through the reader pipeline, or only
+    /// check it is unchanged when `fetch.revalidate` carries the
+    /// validators of a cached render. The reply port carries the result (or the pipeline
+    /// error). Concurrent identical requests share a single render.
     Render {
         url: String,
         min_id: String,
         mode: RenderMode,
-        reply: RpcReplyPort<Result<String, PipelineError>>,
+        fetch: FetchOptions,
+        reply: RpcReplyPort<PageResult>,
+    },
+    /// Render HTML the caller already holds (a DOM snapshot from the
+    /// extension) without fetching `url`.
+    RenderHtml {
+        url: String,
+        html: String,
+        min_id: String,
+        mode: RenderMode,
+        reply: RpcReplyPort<RenderResult<String>>,
     },
+    /// Sent by the task running a `Render` once it completes, so the
+    /// actor can answer every caller waiting on the same request.
+    Rendered { key: RenderKey, result: PageResult },
 }
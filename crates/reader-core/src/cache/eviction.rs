@@ -0,0 +1,215 @@
+//! Size budget for the on-disk caches.
+//!
+//! Every file written to the render cache or the AVIF image cache, and
+//! every submitted DOM snapshot, is recorded in the `cache_entries` table
+//! with its size and the time it was last served. [`evict`] then trims
+//! each cache back under its configured budget by deleting the least
+//! recently used entries; the server runs it periodically in the
+//! background, and after each snapshot is stored. Renders may still
+//! point at an evicted image: `/i/` then redirects to the original.
+
+use std::path::Path;
+
+use rusqlite::params;
+
+use crate::{cache_error::CacheError, config::CONFIG};
+
+use super::{now, Result, DB};
+
+/// Which cache an entry belongs to. Each kind has its own budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheKind {
+    /// Rendered articles under `cache_folder/pages`.
+    Render,
+    /// Re-encoded images under `cache_folder/images`.
+    Image,
+    /// Rows of the `snapshots` table, tracked by short id.
+    Snapshot,
+}
+
+impl CacheKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Render => "render",
+            Self::Image => "image",
+            Self::Snapshot => "snapshot",
+        }
+    }
+
+    /// `(max_bytes, max_entries)` from the config; 0 means unlimited.
+    fn budget(self) -> (u64, u64) {
+        match self {
+            Self::Render => (
+                CONFIG.render_cache_max_bytes,
+                CONFIG.render_cache_max_entries,
+            ),
+            Self::Image => (CONFIG.image_cache_max_bytes, CONFIG.image_cache_max_entries),
+            Self::Snapshot => (CONFIG.snapshot_max_bytes, CONFIG.snapshot_max_entries),
+        }
+    }
+}
+
+/// Outcome of one eviction pass over a cache.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Evicted {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Record a file just written to a cache (or, for
+/// [`CacheKind::Snapshot`], a snapshot's short id). Its access time
+/// starts now.
+pub fn record_entry(kind: CacheKind, path: &str, size: u64) -> Result<()> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO cache_entries (path, kind, size, accessed_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![path, kind.as_str(), size as i64, now()],
+    )?;
+    Ok(())
+}
+
+/// Mark a cached file as just served.
+pub fn record_access(path: &str) -> Result<()> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    conn.execute(
+        "UPDATE cache_entries SET accessed_at = ?2 WHERE path = ?1",
+        params![path, now()],
+    )?;
+    Ok(())
+}
+
+/// Start tracking files in `dir` that predate the `cache_entries` table,
+/// using their modification time as the last access. Without this, a
+/// cache filled by an older version would never be evicted.
+pub fn adopt_untracked(kind: CacheKind, dir: &Path) -> Result<usize> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(CacheError::Io(e)),
+    };
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    let mut adopted = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs() as i64);
+        adopted += conn.execute(
+            "INSERT OR IGNORE INTO cache_entries (path, kind, size, accessed_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                entry.path().to_string_lossy(),
+                kind.as_str(),
+                metadata.len() as i64,
+                modified
+            ],
+        )?;
+    }
+    Ok(adopted)
+}
+
+/// Delete least recently used entries of `kind` until the cache fits its
+/// configured budget. The database lock is only held to pick the victims
+/// and to forget them afterwards, not while their files are deleted, so
+/// cache lookups carry on during a pass.
+pub fn evict(kind: CacheKind) -> Result<Evicted> {
+    let (max_bytes, max_entries) = kind.budget();
+    if max_bytes == 0 && max_entries == 0 {
+        return Ok(Evicted::default());
+    }
+    let entries: Vec<(String, u64)> = {
+        let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+        let mut statement = conn.prepare(
+            "SELECT path, size FROM cache_entries WHERE kind = ?1
+             ORDER BY accessed_at DESC, path",
+        )?;
+        let entries = statement
+            .query_map(params![kind.as_str()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        entries
+    };
+
+    let removed: Vec<&(String, u64)> = over_budget(&entries, max_bytes, max_entries)
+        .iter()
+        .filter(|(path, _)| kind == CacheKind::Snapshot || remove_file(path))
+        .collect();
+
+    let mut conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    let transaction = conn.transaction()?;
+    let mut evicted = Evicted::default();
+    for (path, size) in removed {
+        transaction.execute("DELETE FROM cache_entries WHERE path = ?1", params![path])?;
+        transaction.execute("DELETE FROM renders WHERE path = ?1", params![path])?;
+        if kind == CacheKind::Snapshot {
+            transaction.execute("DELETE FROM snapshots WHERE short = ?1", params![path])?;
+        }
+        evicted.entries += 1;
+        evicted.bytes += size;
+    }
+    transaction.commit()?;
+    Ok(evicted)
+}
+
+/// Delete an evicted file; one already gone counts as deleted.
+fn remove_file(path: &str) -> bool {
+    match std::fs::remove_file(path) {
+        Ok(()) => true,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+        Err(e) => {
+            eprintln!("evict {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Given entries ordered most recently used first, the tail that doesn't
+/// fit in `max_bytes` / `max_entries` (0 meaning no limit).
+fn over_budget(entries: &[(String, u64)], max_bytes: u64, max_entries: u64) -> &[(String, u64)] {
+    let mut total = 0;
+    for (index, (_, size)) in entries.iter().enumerate() {
+        total += size;
+        let too_many = max_entries != 0 && index as u64 >= max_entries;
+        let too_big = max_bytes != 0 && total > max_bytes;
+        if too_many || too_big {
+            return &entries[index..];
+        }
+    }
+    &[]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(sizes: &[u64]) -> Vec<(String, u64)> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| (format!("e{}", i), size))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_most_recent_entries_within_byte_budget() {
+        let list = entries(&[40, 40, 40, 10]);
+        let evict = over_budget(&list, 100, 0);
+        assert_eq!(evict.len(), 2);
+        assert_eq!(evict[0].0, "e2");
+    }
+
+    #[test]
+    fn entry_limit_and_unlimited_budgets() {
+        let list = entries(&[1, 1, 1]);
+        assert_eq!(over_budget(&list, 0, 2).len(), 1);
+        assert!(over_budget(&list, 0, 0).is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+//! URL short-id store, submitted DOM snapshots, and the optional on-disk
+//! HTML cache. [`eviction`] keeps that cache and the image cache within
+//! their size budgets.
+//!
+//! This module owns nothing beyond a SQLite connection and a small
+//! filesystem helper. It does not know about the rendering pipeline — the
+//! server orchestrates "cache miss → call page actor → store result".
+
+use std::{
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    cache_error::CacheError, config::CONFIG, hash::sha256, http::Validators,
+    pipeline::PIPELINE_VERSION, render_mode::RenderMode,
+};
+
+mod eviction;
+
+pub use eviction::{adopt_untracked, evict, record_access, record_entry, CacheKind, Evicted};
+
+type Result<T> = std::result::Result<T, CacheError>;
+
+/// Length (in hex chars) of the short id used in `/m/{…}` URLs. 6 hex
+/// chars = 24 bits of collision space, which is fine for a single-user
+/// reader instance and keeps URLs compact.
+const SHORT_ID_LEN: usize = 6;
+
+// Startup-time panic is acceptable here: a server with no URL store is
+// fundamentally unusable, and a clear failure at boot is easier to diagnose
+// than a cascade of "SQLite not initialised" errors at request time.
+static DB: Lazy<Mutex<Connection>> = Lazy::new(|| {
+    if let Some(parent) = std::path::Path::new(&CONFIG.database_file).parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let conn = Connection::open(&CONFIG.database_file).expect("open sqlite");
+    init_schema(&conn).expect("init sqlite schema");
+    Mutex::new(conn)
+});
+
+/// Create the tables and indexes `DB` relies on, if they don't exist.
+fn init_schema(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         CREATE TABLE IF NOT EXISTS urls (
+             short TEXT PRIMARY KEY,
+             url   TEXT NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS canonical_urls (
+             url       TEXT PRIMARY KEY,
+             canonical TEXT NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS image_sources (
+             short TEXT PRIMARY KEY,
+             url   TEXT NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS snapshots (
+             short TEXT PRIMARY KEY,
+             url   TEXT NOT NULL,
+             html  TEXT NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS renders (
+             path          TEXT PRIMARY KEY,
+             fetched_at    INTEGER NOT NULL,
+             etag          TEXT,
+             last_modified TEXT
+         );
+         CREATE TABLE IF NOT EXISTS cache_entries (
+             path        TEXT PRIMARY KEY,
+             kind        TEXT NOT NULL,
+             size        INTEGER NOT NULL,
+             accessed_at INTEGER NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS cache_entries_lru
+             ON cache_entries (kind, accessed_at);
+         -- Superseded by persistent_cookies: its rows had neither a path
+         -- nor an absolute expiry.
+         DROP TABLE IF EXISTS cookies;
+         CREATE TABLE IF NOT EXISTS persistent_cookies (
+             domain     TEXT NOT NULL,
+             path       TEXT NOT NULL,
+             name       TEXT NOT NULL,
+             url        TEXT NOT NULL,
+             set_cookie TEXT NOT NULL,
+             expires_at INTEGER,
+             PRIMARY KEY (domain, path, name)
+         );",
+    )
+}
+
+pub fn get_url_for_shortened(shortened: &str) -> Result<Option<String>> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    Ok(conn
+        .query_row(
+            "SELECT url FROM urls WHERE short = ?1",
+            params![shortened],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?)
+}
+
+/// Short id for `url`. A URL already known to be an alias of another
+/// (see [`record_canonical`]) gets the canonical URL's id, which renders
+/// a fetch of the canonical URL itself.
+pub fn get_shortened_from_url(url: &str) -> Result<String> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    Ok(shortened_from_url(&conn, url)?)
+}
+
+fn shortened_from_url(conn: &Connection, url: &str) -> rusqlite::Result<String> {
+    let canonical = conn
+        .query_row(
+            "SELECT canonical FROM canonical_urls WHERE url = ?1",
+            params![url],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+    let url = canonical.as_deref().unwrap_or(url);
+    let short = sha256(url)[..SHORT_ID_LEN].to_owned();
+    conn.execute(
+        "INSERT OR IGNORE INTO urls (short, url) VALUES (?1, ?2)",
+        params![short, url],
+    )?;
+    Ok(short)
+}
+
+/// Record that the page fetched from `url` declared `canonical` as its
+/// URL, so later lookups of `url` resolve to the canonical short id.
+/// Nothing else moves: short ids already handed out for `url` keep
+/// rendering `url`, and no render of `url` is filed under `canonical`,
+/// since a page may name any URL on its host as its canonical.
+pub fn record_canonical(url: &str, canonical: &str) -> Result<()> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    Ok(record_alias(&conn, url, canonical)?)
+}
+
+fn record_alias(conn: &Connection, url: &str, canonical: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO canonical_urls (url, canonical) VALUES (?1, ?2)",
+        params![url, canonical],
+    )?;
+    conn.execute(
+        "DELETE FROM canonical_urls WHERE url = ?1",
+        params![canonical],
+    )?;
+    Ok(())
+}
+
+/// Record that `/i/{short}` serves a re-encode of the image at `url`, so
+/// it can send readers to the original once the re-encode is evicted.
+pub fn record_image_source(short: &str, url: &str) -> Result<()> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    conn.execute(
+        "INSERT OR IGNORE INTO image_sources (short, url) VALUES (?1, ?2)",
+        params![short, url],
+    )?;
+    Ok(())
+}
+
+/// The original URL of the image behind `/i/{short}`.
+pub fn get_image_source(short: &str) -> Result<Option<String>> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    Ok(conn
+        .query_row(
+            "SELECT url FROM image_sources WHERE short = ?1",
+            params![short],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?)
+}
+
+/// Record a DOM snapshot submitted by the browser extension and return
+/// its short id. The id hashes the content as well as the URL, so a
+/// snapshot never shadows the server-side fetch of the same page and two
+/// different snapshots of one page stay distinct. Snapshots count
+/// against their own eviction budget, enforced before returning.
+pub fn store_snapshot(url: &str, html: &str) -> Result<String> {
+    let short = sha256(&format!("{}\n{}", url, html))[..SHORT_ID_LEN].to_owned();
+    {
+        let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+        conn.execute(
+            "INSERT OR IGNORE INTO urls (short, url) VALUES (?1, ?2)",
+            params![short, url],
+        )?;
+        conn.execute(
+            "INSERT OR REPLACE INTO snapshots (short, url, html) VALUES (?1, ?2, ?3)",
+            params![short, url, html],
+        )?;
+    }
+    record_entry(CacheKind::Snapshot, &short, html.len() as u64)?;
+    evict(CacheKind::Snapshot)?;
+    Ok(short)
+}
+
+/// The submitted HTML behind a snapshot short id, or `None` for an
+/// ordinary fetch-on-demand id.
+pub fn get_snapshot(short: &str) -> Result<Option<String>> {
+    let html = {
+        let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+        conn.query_row(
+            "SELECT html FROM snapshots WHERE short = ?1",
+            params![short],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+    };
+    if html.is_some() {
+        record_access(short)?;
+    }
+    Ok(html)
+}
+
+/// A persistent cookie as stored. Cookies are told apart by domain, path
+/// and name, as a jar does; `set_cookie` carries an absolute `Expires`
+/// in place of any `Max-Age`, so replaying it later does not restart its
+/// lifetime.
+#[derive(Debug)]
+pub struct StoredCookie {
+    pub domain: String,
+    pub path: String,
+    pub name: String,
+    /// The URL the cookie was received from.
+    pub url: String,
+    pub set_cookie: String,
+    /// Unix seconds after which the cookie is gone; `None` for a session
+    /// cookie.
+    pub expires_at: Option<i64>,
+}
+
+/// Persist a cookie received for a persistent cookie domain, replacing
+/// any earlier one with the same domain, path and name.
+pub fn store_cookie(cookie: &StoredCookie) -> Result<()> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO persistent_cookies
+             (domain, path, name, url, set_cookie, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            cookie.domain,
+            cookie.path,
+            cookie.name,
+            cookie.url,
+            cookie.set_cookie,
+            cookie.expires_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Every live persisted cookie as `(url it came from, Set-Cookie value)`.
+/// Expired rows are deleted first.
+pub fn load_cookies() -> Result<Vec<(String, String)>> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    conn.execute(
+        "DELETE FROM persistent_cookies WHERE expires_at <= ?1",
+        params![now()],
+    )?;
+    let mut statement = conn.prepare("SELECT url, set_cookie FROM persistent_cookies")?;
+    let cookies = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(cookies)
+}
+
+/// Is on-disk HTML caching enabled by config?
+pub fn is_enabled() -> bool {
+    CONFIG.enable_cache
+}
+
+/// Directory under `cache_folder` holding rendered pages, one
+/// subdirectory per [`generation`].
+const PAGES_DIR: &str = "pages";
+
+/// Identifies everything besides the article itself that shapes a
+/// render: the pipeline version and the config knobs the output depends
+/// on. Renders live in a directory named after it, so changing any of
+/// these orphans every older entry at once.
+pub fn generation() -> String {
+    let fingerprint = format!(
+        "v{}\nrecompress_images={}\ndownload_image_budget={}",
+        PIPELINE_VERSION, CONFIG.recompress_images, CONFIG.download_image_budget
+    );
+    format!("v{}-{}", PIPELINE_VERSION, &sha256(&fingerprint)[..8])
+}
+
+/// Deterministic cache path for a render. `key` is the article URL, or
+/// the short id for submitted snapshots. The mode is part of the file
+/// name, so a View render (with `/m/` links) is never served as a
+/// Download, and the directory is the current [`generation`].
+pub fn cache_path(key: &str, mode: RenderMode) -> String {
+    format!(
+        "{}/{}/{}/{}.{}.{}",
+        CONFIG.cache_folder,
+        PAGES_DIR,
+        generation(),
+        sha256(key),
+        mode.name(),
+        mode.extension()
+    )
+}
+
+/// Delete renders from other generations, along with the flat
+/// `{sha256}.html` files older versions wrote straight into
+/// `cache_folder`. Called once at startup; returns how many entries were
+/// removed.
+pub fn purge_stale() -> Result<usize> {
+    let root = std::path::Path::new(&CONFIG.cache_folder);
+    let mut removed = 0;
+    match std::fs::read_dir(root.join(PAGES_DIR)) {
+        Ok(entries) => {
+            let current = generation();
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_name().to_string_lossy() != current {
+                    std::fs::remove_dir_all(entry.path())?;
+                    removed += 1;
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(CacheError::Io(e)),
+    }
+    match std::fs::read_dir(root) {
+        Ok(entries) => {
+            for entry in entries {
+                let path = entry?.path();
+                let legacy = path.is_file()
+                    && path
+                        .extension()
+                        .is_some_and(|ext| ext == "html" || ext == "md" || ext == "xhtml");
+                if legacy {
+                    std::fs::remove_file(path)?;
+                    removed += 1;
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(CacheError::Io(e)),
+    }
+    let current = format!("{}/{}/{}/%", CONFIG.cache_folder, PAGES_DIR, generation());
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    conn.execute(
+        "DELETE FROM renders WHERE path NOT LIKE ?1",
+        params![current],
+    )?;
+    conn.execute(
+        "DELETE FROM cache_entries WHERE kind = ?1 AND path NOT LIKE ?2",
+        params![CacheKind::Render.as_str(), current],
+    )?;
+    Ok(removed)
+}
+
+/// A render read back from the disk cache.
+pub struct CachedRender {
+    pub body: String,
+    /// Unix time of the origin fetch the render came from.
+    pub fetched_at: i64,
+    pub validators: Validators,
+}
+
+impl CachedRender {
+    /// Still within `cache_ttl` of its fetch, so it can be served
+    /// without asking the origin.
+    pub fn is_fresh(&self) -> bool {
+        now() - self.fetched_at < CONFIG.cache_ttl as i64
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Try to read a cached render from disk. Returns `Ok(None)` on a miss so
+/// the caller can tell that apart from a hard I/O error. A file with no
+/// recorded fetch is treated as fetched at the epoch, i.e. stale.
+pub async fn try_cached(key: &str, mode: RenderMode) -> Result<Option<CachedRender>> {
+    if !is_enabled() {
+        return Ok(None);
+    }
+    let path = cache_path(key, mode);
+    let body = match tokio::fs::read_to_string(&path).await {
+        Ok(body) => body,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(CacheError::Io(e)),
+    };
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    let (fetched_at, validators) = conn
+        .query_row(
+            "SELECT fetched_at, etag, last_modified FROM renders WHERE path = ?1",
+            params![path],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    Validators {
+                        etag: row.get(1)?,
+                        last_modified: row.get(2)?,
+                    },
+                ))
+            },
+        )
+        .optional()?
+        .unwrap_or_default();
+    drop(conn);
+    record_access(&path)?;
+    Ok(Some(CachedRender {
+        body,
+        fetched_at,
+        validators,
+    }))
+}
+
+/// Write a rendered article to disk and record when it was fetched and
+/// with which validators. Best-effort: a failed write is logged but does
+/// not propagate, because the in-memory response is still valid.
+pub async fn store(key: &str, mode: RenderMode, html: &str, validators: &Validators) {
+    if !is_enabled() {
+        return;
+    }
+    let path = cache_path(key, mode);
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        if let Err(e) = tokio::fs::create_dir_all(parent).await {
+            eprintln!("mkdir cache {}: {}", parent.display(), e);
+            return;
+        }
+    }
+    if let Err(e) = tokio::fs::write(&path, html).await {
+        eprintln!("cache write {}: {}", path, e);
+        return;
+    }
+    let recorded = DB
+        .lock()
+        .map_err(|_| CacheError::MutexPoisoned)
+        .and_then(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO renders (path, fetched_at, etag, last_modified)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![path, now(), validators.etag, validators.last_modified],
+            )
+            .map_err(CacheError::from)
+        })
+        .and_then(|_| record_entry(CacheKind::Render, &path, html.len() as u64));
+    if let Err(e) = recorded {
+        eprintln!("cache record {}: {}", path, e);
+    }
+}
+
+/// Mark a cached render as just revalidated, restarting its TTL.
+pub fn touch(key: &str, mode: RenderMode) -> Result<()> {
+    let conn = DB.lock().map_err(|_| CacheError::MutexPoisoned)?;
+    conn.execute(
+        "UPDATE renders SET fetched_at = ?2 WHERE path = ?1",
+        params![cache_path(key, mode), now()],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url_of(conn: &Connection, short: &str) -> String {
+        conn.query_row(
+            "SELECT url FROM urls WHERE short = ?1",
+            params![short],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn a_canonical_alias_never_repoints_another_page() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let victim = "https://blog.example/~alice/post";
+        let attacker = "https://blog.example/~mallory/post";
+        let victim_short = shortened_from_url(&conn, victim).unwrap();
+        let attacker_short = shortened_from_url(&conn, attacker).unwrap();
+
+        // Mallory's page claims Alice's post as its canonical URL.
+        record_alias(&conn, attacker, victim).unwrap();
+
+        assert_eq!(url_of(&conn, &victim_short), victim);
+        assert_eq!(url_of(&conn, &attacker_short), attacker);
+        // Only new lookups of the alias go to the canonical URL, which is
+        // then fetched for what it is.
+        assert_eq!(shortened_from_url(&conn, attacker).unwrap(), victim_short);
+    }
+
+    #[test]
+    fn a_snapshot_counts_against_the_snapshot_budget() {
+        let html = "<html><body><p>tracked snapshot</p></body></html>";
+        let short = store_snapshot("https://snapshot.example/tracked", html).unwrap();
+        let (kind, size): (String, i64) = DB
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT kind, size FROM cache_entries WHERE path = ?1",
+                params![short],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(kind, CacheKind::Snapshot.as_str());
+        assert_eq!(size as usize, html.len());
+        assert_eq!(get_snapshot(&short).unwrap().as_deref(), Some(html));
+    }
+}
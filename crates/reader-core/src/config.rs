@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{collections::BTreeMap, path::PathBuf};
 
 use once_cell::sync::Lazy;
 
@@ -10,6 +10,250 @@ pub struct Config {
     pub database_file: String,
     pub address: String,
     pub max_size: u64,
+    /// Upper bound, in bytes of encoded `data:` URI, on the images a
+    /// single `/d/` download may embed. Images past the budget are
+    /// replaced by a captioned placeholder linking to the original.
+    #[serde(default = "default_download_image_budget")]
+    pub download_image_budget: u64,
+    /// Hostnames, IPs or CIDR ranges that outbound fetches may reach even
+    /// though they fall in a loopback, private or link-local range.
+    #[serde(default)]
+    pub fetch_allowlist: Vec<String>,
+    /// Extra IPs or CIDR ranges outbound fetches must never reach, on top
+    /// of the built-in private ranges.
+    #[serde(default)]
+    pub fetch_denylist: Vec<String>,
+    /// Number of image fetch + AVIF encode worker threads.
+    #[serde(default = "default_image_workers")]
+    pub image_workers: usize,
+    /// Image jobs that may wait for a free worker. Images past this are
+    /// served from their original URL instead of being re-encoded.
+    #[serde(default = "default_image_queue_size")]
+    pub image_queue_size: usize,
+    /// Seconds a cached render is served as-is. After that the origin is
+    /// asked whether the page changed (`If-None-Match` /
+    /// `If-Modified-Since`) and the article is re-rendered only if it did.
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl: u64,
+    /// Budget for cached renders under `cache_folder/pages`, in bytes
+    /// and in files. Least recently served renders are evicted past
+    /// either limit; 0 disables that limit.
+    #[serde(default = "default_render_cache_max_bytes")]
+    pub render_cache_max_bytes: u64,
+    #[serde(default)]
+    pub render_cache_max_entries: u64,
+    /// Same budget for re-encoded images under `cache_folder/images`.
+    #[serde(default = "default_image_cache_max_bytes")]
+    pub image_cache_max_bytes: u64,
+    #[serde(default)]
+    pub image_cache_max_entries: u64,
+    /// Same budget for DOM snapshots posted to `/api/render`, stored in
+    /// SQLite and counted by HTML size.
+    #[serde(default = "default_snapshot_max_bytes")]
+    pub snapshot_max_bytes: u64,
+    #[serde(default = "default_snapshot_max_entries")]
+    pub snapshot_max_entries: u64,
+    /// Seconds between background eviction passes.
+    #[serde(default = "default_cache_eviction_interval")]
+    pub cache_eviction_interval: u64,
+    /// Serve `/record/{short}`, which renders an article while saving
+    /// every response it needs to `fixture_folder/{short}`, and
+    /// `/replay/{name}`, which renders such a bundle offline.
+    #[serde(default)]
+    pub enable_fixtures: bool,
+    #[serde(default = "default_fixture_folder")]
+    pub fixture_folder: String,
+    /// Seconds an outbound fetch may spend establishing a connection.
+    #[serde(default = "default_fetch_connect_timeout")]
+    pub fetch_connect_timeout: u64,
+    /// Seconds an outbound fetch may wait for the response headers or
+    /// the next chunk of the body.
+    #[serde(default = "default_fetch_read_timeout")]
+    pub fetch_read_timeout: u64,
+    /// Seconds an outbound fetch may take end to end, body included.
+    #[serde(default = "default_fetch_total_timeout")]
+    pub fetch_total_timeout: u64,
+    /// How many times a fetch that failed transiently (connection reset,
+    /// timeout, `429` / `502` / `503` / `504`) is retried.
+    #[serde(default = "default_fetch_retries")]
+    pub fetch_retries: u32,
+    /// Milliseconds before the first retry; doubled for each one after.
+    #[serde(default = "default_fetch_retry_base_delay_ms")]
+    pub fetch_retry_base_delay_ms: u64,
+    /// Longest wait in seconds before a retry. An origin asking for a
+    /// longer `Retry-After` is not retried at all.
+    #[serde(default = "default_fetch_retry_max_delay")]
+    pub fetch_retry_max_delay: u64,
+    /// Fetches allowed in flight to one host at a time, articles and
+    /// images together. 0 means unlimited.
+    #[serde(default = "default_fetch_host_concurrency")]
+    pub fetch_host_concurrency: usize,
+    /// Seconds a request waits for its render, downloads included,
+    /// before failing.
+    #[serde(default = "default_render_timeout")]
+    pub render_timeout: u64,
+    /// Politeness rules for article fetches.
+    #[serde(default)]
+    pub robots: Robots,
+    /// Headers outbound fetches identify themselves with.
+    #[serde(default)]
+    pub identity: Identity,
+}
+
+impl Config {
+    /// Check what deserializing can't, so a bad entry stops the server at
+    /// startup rather than failing the first fetch that needs it.
+    fn validate(&self) -> Result<(), String> {
+        crate::http::guard::Policy::new(&self.fetch_allowlist, &self.fetch_denylist)?;
+        crate::http::identity::validate(&self.identity)
+    }
+}
+
+fn default_fixture_folder() -> String {
+    "data/fixtures".into()
+}
+
+fn default_fetch_connect_timeout() -> u64 {
+    10
+}
+
+fn default_fetch_read_timeout() -> u64 {
+    20
+}
+
+fn default_fetch_total_timeout() -> u64 {
+    60
+}
+
+fn default_fetch_retries() -> u32 {
+    2
+}
+
+fn default_fetch_retry_base_delay_ms() -> u64 {
+    500
+}
+
+fn default_fetch_retry_max_delay() -> u64 {
+    30
+}
+
+fn default_fetch_host_concurrency() -> usize {
+    4
+}
+
+fn default_render_timeout() -> u64 {
+    300
+}
+
+/// The `[robots]` table: opt-in crawl politeness for instances run as a
+/// shared proxy. When enabled, article fetches honour each host's
+/// `robots.txt` for `agent` and are spaced at least `min_delay_ms`
+/// apart per host (longer if `robots.txt` asks for a `Crawl-delay`).
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct Robots {
+    pub enabled: bool,
+    /// Product token matched against `User-agent` lines.
+    pub agent: String,
+    pub min_delay_ms: u64,
+    /// Seconds a fetched `robots.txt` is trusted before refetching.
+    pub cache_ttl: u64,
+}
+
+impl Default for Robots {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            agent: String::from("clean_reader"),
+            min_delay_ms: 1000,
+            cache_ttl: 86400,
+        }
+    }
+}
+
+/// The `[identity]` table: what outbound requests send as
+/// `User-Agent`, `Accept-Language` and any extra headers, with
+/// per-domain overrides under `[identity.domains."example.com"]`.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct Identity {
+    pub user_agent: String,
+    pub accept_language: String,
+    pub headers: BTreeMap<String, String>,
+    /// Send the reader's own `Accept-Language` instead of
+    /// `accept_language` when rendering for them. Renders are then cached
+    /// per language.
+    pub forward_accept_language: bool,
+    /// Domains (and their subdomains) whose cookies outlive a render:
+    /// kept in SQLite and sent on every later fetch, so an account the
+    /// instance is logged into stays logged in. Everyone else's cookies
+    /// last one render.
+    pub persistent_cookie_domains: Vec<String>,
+    /// Overrides for a domain and its subdomains. The most specific
+    /// matching domain wins.
+    pub domains: BTreeMap<String, DomainIdentity>,
+}
+
+impl Default for Identity {
+    fn default() -> Self {
+        Self {
+            user_agent: String::from(DEFAULT_USER_AGENT),
+            accept_language: String::from("en-US,en;q=0.9"),
+            headers: BTreeMap::new(),
+            forward_accept_language: false,
+            persistent_cookie_domains: Vec::new(),
+            domains: BTreeMap::new(),
+        }
+    }
+}
+
+/// Per-domain identity override. Unset fields fall back to [`Identity`];
+/// `headers` are added on top of the global extra headers.
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+pub struct DomainIdentity {
+    pub user_agent: Option<String>,
+    pub accept_language: Option<String>,
+    pub headers: BTreeMap<String, String>,
+}
+
+const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+
+fn default_download_image_budget() -> u64 {
+    4_000_000
+}
+
+fn default_image_workers() -> usize {
+    4
+}
+
+fn default_image_queue_size() -> usize {
+    256
+}
+
+fn default_cache_ttl() -> u64 {
+    3600
+}
+
+fn default_render_cache_max_bytes() -> u64 {
+    1_000_000_000
+}
+
+fn default_image_cache_max_bytes() -> u64 {
+    4_000_000_000
+}
+
+fn default_snapshot_max_bytes() -> u64 {
+    200_000_000
+}
+
+fn default_snapshot_max_entries() -> u64 {
+    10_000
+}
+
+fn default_cache_eviction_interval() -> u64 {
+    600
 }
 
 /// Default config written out the first time the server starts in a fresh
@@ -21,13 +265,63 @@ cache_folder = "data/cache"
 database_file = "data/db.sqlite"
 address = "127.0.0.1:8080"
 max_size = 8048576
+download_image_budget = 4000000
+fetch_allowlist = []
+fetch_denylist = []
+image_workers = 4
+image_queue_size = 256
+cache_ttl = 3600
+render_cache_max_bytes = 1000000000
+render_cache_max_entries = 0
+image_cache_max_bytes = 4000000000
+image_cache_max_entries = 0
+snapshot_max_bytes = 200000000
+snapshot_max_entries = 10000
+cache_eviction_interval = 600
+enable_fixtures = false
+fixture_folder = "data/fixtures"
+fetch_connect_timeout = 10
+fetch_read_timeout = 20
+fetch_total_timeout = 60
+fetch_retries = 2
+fetch_retry_base_delay_ms = 500
+fetch_retry_max_delay = 30
+fetch_host_concurrency = 4
+render_timeout = 300
+
+[robots]
+enabled = false
+agent = "clean_reader"
+min_delay_ms = 1000
+cache_ttl = 86400
+
+[identity]
+user_agent = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36"
+accept_language = "en-US,en;q=0.9"
+forward_accept_language = false
+persistent_cookie_domains = []
+
+[identity.headers]
+
+# [identity.domains."example.com"]
+# accept_language = "fr-FR,fr;q=0.9"
+# headers = { "X-Example" = "1" }
 "#;
 
+/// Environment variable naming the config file to load instead of
+/// `config.toml` in the working directory.
+pub const CONFIG_ENV: &str = "CLEAN_READER_CONFIG";
+
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
-    let path = Path::new("config.toml");
+    let path =
+        std::env::var_os(CONFIG_ENV).map_or_else(|| PathBuf::from("config.toml"), PathBuf::from);
     if !path.exists() {
-        std::fs::write(path, DEFAULT_CONFIG).expect("write default config.toml");
+        std::fs::write(&path, DEFAULT_CONFIG).expect("write default config");
+    }
+    let contents = std::fs::read_to_string(&path).expect("read config");
+    let config: Config = toml::from_str(&contents).expect("parse config");
+    if let Err(e) = config.validate() {
+        panic!("invalid config {}: {}", path.display(), e);
     }
-    let contents = std::fs::read_to_string(path).expect("read config.toml");
-    toml::from_str(&contents).expect("parse config.toml")
+    config
 });
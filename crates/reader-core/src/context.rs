@@ -1,25 +1,32 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
 use reqwest::Url;
 
-use crate::{render_mode::RenderMode, title_extractor::ArticleData};
+use crate::{
+    http::Fetcher, image::PendingImage, render_mode::RenderMode, title_extractor::ArticleData,
+};
 
 /// Mutable context threaded through the text-compound lowering and
-/// HTML-compilation passes. Holds the source URL (for link
-/// absolutization), the render mode, an anchor-renaming map, and the page
-/// metadata.
+/// HTML-compilation passes. Holds the source URL, the document base URL
+/// (for link absolutization), the render mode, an anchor-renaming map, the page
+/// metadata, the fetcher images are downloaded through, and the images
+/// the body shows, which self-contained modes then inline.
 #[derive(Clone)]
 pub struct Context<'a> {
     pub url: Url,
+    /// `<base href>` of the document, or `url` when it has none.
+    pub base: Url,
     pub mode: RenderMode,
     pub min_id: String,
     pub map: HashMap<&'a str, usize>,
     pub count: usize,
     pub meta: ArticleData,
+    pub fetcher: Arc<dyn Fetcher>,
+    pub pending_images: Vec<PendingImage>,
 }
 
 impl<'a> Context<'a> {
-    /// Resolve a potentially-relative link against the article URL and
+    /// Resolve a potentially-relative link against the base URL and
     /// rewrite `#fragment` anchors to dedup-friendly numeric ids.
     pub fn absolutize(&mut self, url: &'a str) -> Cow<'a, str> {
         if let Some(fragment) = url.strip_prefix('#') {
@@ -31,7 +38,7 @@ impl<'a> Context<'a> {
                 Cow::Owned(format!("#{}", self.count))
             }
         } else {
-            self.url
+            self.base
                 .join(url)
                 .map(|joined| Cow::Owned(joined.to_string()))
                 .unwrap_or_else(|_| Cow::Borrowed(url))
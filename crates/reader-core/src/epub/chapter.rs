@@ -0,0 +1,122 @@
+//! Pull the pieces an EPUB needs out of a rendered XHTML chapter: its
+//! title, its heading outline, and its inlined images.
+//!
+//! The chapter was produced by our own compiler, so the markup shapes
+//! scanned for here (`<h2 id="…">`, `src="data:…"`) are ones we emit
+//! ourselves rather than arbitrary web HTML.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+use super::EpubError;
+
+static TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<title>(.*?)</title>").unwrap());
+static LANGUAGE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<html [^>]*\blang="([^"]+)""#).unwrap());
+static HEADING: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?s)<h([1-5]) id="([^"]+)">(.*?)</h[1-5]>"#).unwrap());
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+static DATA_URI: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"src="data:([a-z0-9.+/-]+);base64,([A-Za-z0-9+/=]+)""#).unwrap());
+
+/// One heading, for the navigation document. `text` is already
+/// XML-escaped, because it was lifted straight out of the chapter.
+pub struct OutlineEntry {
+    pub level: usize,
+    pub id: String,
+    pub text: String,
+}
+
+/// An image resource extracted from a chapter's `data:` URIs.
+pub struct ChapterImage {
+    pub href: String,
+    pub media_type: String,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Chapter {
+    pub href: String,
+    pub title: String,
+    /// The article's language tag, `und` when it didn't say.
+    pub language: String,
+    pub document: String,
+    pub outline: Vec<OutlineEntry>,
+    pub images: Vec<ChapterImage>,
+}
+
+impl Chapter {
+    /// Parse the `number`th (1-based) chapter document.
+    pub fn parse(number: usize, xhtml: &str) -> Result<Self, EpubError> {
+        let title = TITLE
+            .captures(xhtml)
+            .map(|caps| caps[1].trim().to_owned())
+            .unwrap_or_else(|| format!("Article {}", number));
+        let language = LANGUAGE
+            .captures(xhtml)
+            .map_or_else(|| "und".to_owned(), |caps| caps[1].to_owned());
+        let outline = HEADING
+            .captures_iter(xhtml)
+            .map(|caps| OutlineEntry {
+                level: caps[1].parse().unwrap_or(1),
+                id: caps[2].to_owned(),
+                text: TAG.replace_all(&caps[3], "").trim().to_owned(),
+            })
+            .filter(|entry| !entry.text.is_empty())
+            .collect();
+        let (document, images) = extract_images(number, xhtml)?;
+        Ok(Self {
+            href: format!("chapter-{}.xhtml", number),
+            title,
+            language,
+            document,
+            outline,
+            images,
+        })
+    }
+}
+
+/// Replace every `src="data:…"` with a relative path to a container
+/// entry, decoding the payload once per distinct URI.
+fn extract_images(number: usize, xhtml: &str) -> Result<(String, Vec<ChapterImage>), EpubError> {
+    let mut images = Vec::new();
+    let mut hrefs: HashMap<String, String> = HashMap::new();
+    let mut error = None;
+    let document = DATA_URI.replace_all(xhtml, |caps: &Captures| {
+        if let Some(href) = hrefs.get(&caps[0]) {
+            return format!("src=\"{}\"", href);
+        }
+        let bytes = match base64::decode(&caps[2]) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error.get_or_insert(e);
+                return String::from("src=\"\"");
+            }
+        };
+        let href = format!(
+            "images/{}-{}.{}",
+            number,
+            images.len() + 1,
+            extension_for(&caps[1])
+        );
+        images.push(ChapterImage {
+            href: href.clone(),
+            media_type: caps[1].to_owned(),
+            bytes,
+        });
+        hrefs.insert(caps[0].to_owned(), href.clone());
+        format!("src=\"{}\"", href)
+    });
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok((document.into_owned(), images)),
+    }
+}
+
+fn extension_for(media_type: &str) -> &str {
+    match media_type {
+        "image/jpeg" => "jpg",
+        "image/svg+xml" => "svg",
+        other => other.rsplit('/').next().unwrap_or("bin"),
+    }
+}
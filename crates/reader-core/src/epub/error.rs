@@ -0,0 +1,15 @@
+/// Errors from assembling an EPUB container.
+#[derive(Debug, thiserror::Error)]
+pub enum EpubError {
+    #[error("Zip write failed: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Inline image is not valid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("An EPUB needs at least one article")]
+    NoChapters,
+}
@@ -0,0 +1,15 @@
+//! EPUB 3 packaging for one or many cleaned articles.
+//!
+//! The pipeline renders each article in [`crate::RenderMode::Epub`] to a
+//! standalone XHTML content document with its images inlined as `data:`
+//! URIs. [`package`] takes those documents as-is, lifts the images out
+//! into their own container entries, builds the navigation document from
+//! the heading ids the compiler assigned, and zips everything into a
+//! `.epub`.
+
+mod chapter;
+mod error;
+mod package;
+
+pub use error::EpubError;
+pub use package::package;
@@ -0,0 +1,322 @@
+//! Zip container layout: `mimetype`, `META-INF/container.xml`, and an
+//! `OEBPS/` directory holding the package document, navigation document,
+//! stylesheet, chapters and images.
+
+use std::{
+    io::{Cursor, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{hash::sha256, highlight};
+
+use super::{
+    chapter::{Chapter, OutlineEntry},
+    EpubError,
+};
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+const STYLESHEET: &str = "body { font-family: serif; line-height: 1.5; }
+img { display: block; max-width: 100%; height: auto; }
+blockquote { border-left: 3px solid #888; margin-left: 0; padding-left: 1em; color: #444; }
+pre { white-space: pre-wrap; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #888; padding: 0.2em 0.5em; }
+.source { font-size: 0.8em; word-break: break-all; }
+";
+
+/// Bundle rendered EPUB chapters (see [`crate::RenderMode::Epub`]) into
+/// a single `.epub` file, in the order given.
+pub fn package(chapters: &[String]) -> Result<Vec<u8>, EpubError> {
+    if chapters.is_empty() {
+        return Err(EpubError::NoChapters);
+    }
+    let chapters = chapters
+        .iter()
+        .enumerate()
+        .map(|(index, xhtml)| Chapter::parse(index + 1, xhtml))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // The mimetype entry must come first and be stored uncompressed so
+    // readers can sniff the container type from a fixed byte offset.
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(package_document(&chapters).as_bytes())?;
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(navigation_document(&chapters).as_bytes())?;
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLESHEET.as_bytes())?;
+    zip.write_all(highlight::LIGHT_STYLESHEET.as_bytes())?;
+    for chapter in &chapters {
+        zip.start_file(format!("OEBPS/{}", chapter.href), deflated)?;
+        zip.write_all(chapter.document.as_bytes())?;
+        for image in &chapter.images {
+            // Images are already compressed; deflating them again only
+            // costs time.
+            zip.start_file(format!("OEBPS/{}", image.href), stored)?;
+            zip.write_all(&image.bytes)?;
+        }
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Book title: the article's own title for a single chapter, otherwise
+/// the first title plus a count of the rest.
+fn book_title(chapters: &[Chapter]) -> String {
+    match chapters {
+        [only] => only.title.clone(),
+        [first, rest @ ..] => format!("{} (+{} more)", first.title, rest.len()),
+        [] => String::new(),
+    }
+}
+
+/// One `<dc:language>` per distinct chapter language, in chapter order.
+/// Chapters that didn't declare one only count if none did.
+fn book_languages(chapters: &[Chapter]) -> String {
+    let mut languages: Vec<&str> = Vec::new();
+    for chapter in chapters {
+        if chapter.language != "und" && !languages.contains(&chapter.language.as_str()) {
+            languages.push(&chapter.language);
+        }
+    }
+    if languages.is_empty() {
+        languages.push("und");
+    }
+    languages
+        .iter()
+        .map(|language| format!("    <dc:language>{}</dc:language>\n", language))
+        .collect()
+}
+
+fn package_document(chapters: &[Chapter]) -> String {
+    let identity: String = chapters.iter().map(|c| c.document.as_str()).collect();
+    let mut manifest = String::from(
+        r#"    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="style" href="style.css" media-type="text/css"/>
+"#,
+    );
+    let mut spine = String::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"chapter-{n}\" href=\"{href}\" media-type=\"application/xhtml+xml\"/>\n",
+            n = index + 1,
+            href = chapter.href
+        ));
+        for (image_index, image) in chapter.images.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"image-{}-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                index + 1,
+                image_index + 1,
+                image.href,
+                image.media_type
+            ));
+        }
+        spine.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", index + 1));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:clean-reader:{id}</dc:identifier>
+    <dc:title>{title}</dc:title>
+{languages}    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+        id = sha256(&identity),
+        title = book_title(chapters),
+        languages = book_languages(chapters),
+        modified = utc_timestamp(SystemTime::now()),
+        manifest = manifest,
+        spine = spine,
+    )
+}
+
+fn navigation_document(chapters: &[Chapter]) -> String {
+    let mut toc = String::from("<ol>");
+    for chapter in chapters {
+        toc.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            chapter.href, chapter.title
+        ));
+        // The first H1 is the article title the chapter entry already
+        // links to; everything after it becomes the nested outline.
+        let headings = match chapter.outline.first() {
+            Some(first) if first.level == 1 => &chapter.outline[1..],
+            _ => &chapter.outline[..],
+        };
+        push_outline(&mut toc, &chapter.href, headings);
+        toc.push_str("</li>");
+    }
+    toc.push_str("</ol>");
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+  <head>
+    <meta charset="UTF-8" />
+    <title>Contents</title>
+  </head>
+  <body>
+    <nav epub:type="toc" id="toc">
+      <h1>Contents</h1>
+      {}
+    </nav>
+  </body>
+</html>
+"#,
+        toc
+    )
+}
+
+/// Write `entries` as nested `<ol>` lists following their heading
+/// levels. A heading deeper than its predecessor opens a sub-list; a
+/// shallower one closes sub-lists until it finds its own depth.
+fn push_outline(out: &mut String, href: &str, entries: &[OutlineEntry]) {
+    let mut open_levels: Vec<usize> = Vec::new();
+    for entry in entries {
+        match open_levels.last() {
+            Some(&top) if entry.level <= top => {
+                out.push_str("</li>");
+                while open_levels.len() > 1 && open_levels.last().is_some_and(|&l| entry.level < l)
+                {
+                    out.push_str("</ol></li>");
+                    open_levels.pop();
+                }
+            }
+            _ => {
+                out.push_str("<ol>");
+                open_levels.push(entry.level);
+            }
+        }
+        out.push_str(&format!(
+            "<li><a href=\"{}#{}\">{}</a>",
+            href, entry.id, entry.text
+        ));
+    }
+    for _ in open_levels {
+        out.push_str("</li></ol>");
+    }
+}
+
+/// Format `time` as the `CCYY-MM-DDThh:mm:ssZ` string EPUB requires
+/// for `dcterms:modified`.
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days (Howard Hinnant), valid for any post-1970 date.
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn entry(level: usize, id: &str) -> OutlineEntry {
+        OutlineEntry {
+            level,
+            id: id.into(),
+            text: id.into(),
+        }
+    }
+
+    #[test]
+    fn outline_nests_by_heading_level() {
+        let mut out = String::new();
+        push_outline(
+            &mut out,
+            "c.xhtml",
+            &[entry(2, "a"), entry(3, "b"), entry(2, "c")],
+        );
+        assert_eq!(
+            out,
+            "<ol><li><a href=\"c.xhtml#a\">a</a><ol><li><a href=\"c.xhtml#b\">b</a></li></ol></li><li><a href=\"c.xhtml#c\">c</a></li></ol>"
+        );
+    }
+
+    #[test]
+    fn timestamp_is_utc_iso8601() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(utc_timestamp(time), "2024-02-29T12:34:56Z");
+    }
+
+    #[test]
+    fn packages_chapter_with_extracted_image() {
+        let chapter = "<?xml version=\"1.0\"?><html><head><title>Hello</title></head><body><h1 id=\"1\">Hello</h1><h2 id=\"2\">Part</h2><img src=\"data:image/png;base64,AAAA\" /></body></html>";
+        let epub = package(&[chapter.to_owned()]).expect("package");
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).expect("valid zip");
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert!(archive.by_name("OEBPS/images/1-1.png").is_ok());
+        let mut document = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name("OEBPS/chapter-1.xhtml").unwrap(),
+            &mut document,
+        )
+        .unwrap();
+        assert!(document.contains("src=\"images/1-1.png\""));
+    }
+
+    #[test]
+    fn book_language_comes_from_the_chapters() {
+        let chapter = |lang: &str| {
+            format!("<?xml version=\"1.0\"?><html xmlns=\"http://www.w3.org/1999/xhtml\"{}><head><title>T</title></head><body></body></html>", lang)
+        };
+        let languages = |documents: &[String]| {
+            let chapters = documents
+                .iter()
+                .enumerate()
+                .map(|(index, xhtml)| Chapter::parse(index + 1, xhtml).unwrap())
+                .collect::<Vec<_>>();
+            book_languages(&chapters)
+        };
+        assert_eq!(
+            languages(&[
+                chapter(" lang=\"de\""),
+                chapter(""),
+                chapter(" lang=\"de\"")
+            ]),
+            "    <dc:language>de</dc:language>\n"
+        );
+        assert_eq!(
+            languages(&[chapter("")]),
+            "    <dc:language>und</dc:language>\n"
+        );
+    }
+}
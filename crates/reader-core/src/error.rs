@@ -1,13 +1,15 @@
 //! Crate-root error aggregator.
 //!
 //! Each module in reader-core has its own narrow error type
-//! (`HttpError`, `NodeError`, `CacheError`, `ImageError`, `PipelineError`).
+//! (`HttpError`, `NodeError`, `CacheError`, `ImageError`, `EpubError`,
+//! `PipelineError`).
 //! This module's `Error` is the union of those — downstream crates that
 //! want a single error type to `?` through can use this one, while
 //! functions internal to the module hierarchy return their narrower
 //! types.
 
 use crate::cache_error::CacheError;
+use crate::epub::EpubError;
 use crate::html_node_error::NodeError;
 use crate::http_error::HttpError;
 use crate::image::ImageError;
@@ -29,6 +31,9 @@ pub enum Error {
     #[error(transparent)]
     Image(#[from] ImageError),
 
+    #[error(transparent)]
+    Epub(#[from] EpubError),
+
     #[error(transparent)]
     Pipeline(#[from] PipelineError),
 
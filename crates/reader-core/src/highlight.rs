@@ -0,0 +1,193 @@
+//! Server-side syntax highlighting for code blocks, so articles need no
+//! script to colour their code. Blocks are tokenised with syntect's
+//! bundled grammars into `<span class="hl-…">` runs; the matching
+//! stylesheet is inlined by the templates.
+//!
+//! The language comes from the page's own hint (`class="language-rust"`,
+//! `lang-py`, …) when there is one. Otherwise [`guess_language`] looks
+//! for a shebang or a few tell-tale constructs, and a block it can't
+//! place stays plain text.
+//!
+//! Readability strips every `class` attribute from the article, so
+//! [`tag_code_languages`] copies the hints into a [`LANGUAGE_ATTR`]
+//! attribute on the raw page before it runs.
+
+use std::borrow::Cow;
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use syntect::{
+    highlighting::ThemeSet,
+    html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// Prefix of every class the highlighter emits, so they can't collide
+/// with the template's own styles.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Class the highlighted `<pre>` carries; the theme's background and
+/// default colour hang off it.
+pub const CODE_CLASS: &str = "hl-code";
+
+/// Attribute carrying a code element's language hint past Readability.
+pub const LANGUAGE_ATTR: &str = "data-language";
+
+// An opening `<pre>` / `<code>` tag with a `class` attribute.
+static CODE_TAG_CLASS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<(?:pre|code)\b[^>]*?\sclass\s*=\s*["']([^"']*)["'][^>]*>"#).unwrap()
+});
+
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+static THEMES: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+
+/// Stylesheet for the dark article page.
+pub static DARK_STYLESHEET: Lazy<String> = Lazy::new(|| stylesheet("base16-ocean.dark"));
+
+/// Stylesheet for EPUB chapters, which readers show on a light page.
+pub static LIGHT_STYLESHEET: Lazy<String> = Lazy::new(|| stylesheet("InspiredGitHub"));
+
+fn stylesheet(theme: &str) -> String {
+    css_for_theme_with_class_style(&THEMES.themes[theme], CLASS_STYLE).unwrap_or_default()
+}
+
+/// Highlight a code block as classed HTML spans, text escaped. `None`
+/// when the language is unknown and can't be guessed; the caller then
+/// writes the block as plain text.
+pub fn highlight(code: &str, language: Option<&str>) -> Option<String> {
+    let syntax = language
+        .and_then(find_syntax)
+        .or_else(|| SYNTAXES.find_syntax_by_first_line(code))
+        .or_else(|| guess_language(code).and_then(find_syntax))?;
+    let mut html = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        html.parse_html_for_line_which_includes_newline(line).ok()?;
+    }
+    Some(html.finalize())
+}
+
+/// The language a `class` attribute declares with a `language-…` or
+/// `lang-…` token.
+pub fn class_language(class: &str) -> Option<&str> {
+    class.split_whitespace().find_map(|token| {
+        token
+            .strip_prefix("language-")
+            .or_else(|| token.strip_prefix("lang-"))
+            .filter(|language| {
+                !language.is_empty()
+                    && language
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+#._-".contains(c))
+            })
+    })
+}
+
+/// Copy the language hint of every `<pre>` / `<code>` tag in `html` into
+/// a [`LANGUAGE_ATTR`] attribute, which Readability leaves alone.
+pub fn tag_code_languages(html: &str) -> Cow<'_, str> {
+    CODE_TAG_CLASS.replace_all(html, |caps: &Captures| {
+        let tag = &caps[0];
+        let Some(language) = class_language(&caps[1]) else {
+            return tag.to_owned();
+        };
+        // The attribute goes before the `/` of a self-closing tag; a `/`
+        // straight after an unquoted value is part of that value.
+        let attributes = &tag[..tag.len() - 1];
+        let (attributes, end) = match attributes.strip_suffix('/') {
+            Some(rest) if rest.ends_with(|c: char| c.is_whitespace() || c == '"' || c == '\'') => {
+                (rest.trim_end(), "/>")
+            }
+            _ => (attributes, ">"),
+        };
+        format!("{} {}=\"{}\"{}", attributes, LANGUAGE_ATTR, language, end)
+    })
+}
+
+/// The grammar for a language hint: a name (`Rust`), a file extension
+/// (`rs`) or one of the common aliases sites use.
+fn find_syntax(language: &str) -> Option<&'static SyntaxReference> {
+    let language = language.to_ascii_lowercase();
+    let token = match language.as_str() {
+        "shell" | "console" | "zsh" => "sh",
+        "js" | "jsx" | "javascript" | "typescript" | "ts" | "node" => "js",
+        "c++" | "cxx" => "cpp",
+        "golang" => "go",
+        "py3" | "python3" => "py",
+        "yml" => "yaml",
+        "rs" | "rust" => "rs",
+        other => other,
+    };
+    SYNTAXES
+        .find_syntax_by_token(token)
+        .or_else(|| SYNTAXES.find_syntax_by_name(&language))
+}
+
+/// Guess the language of an unlabelled block from a handful of
+/// distinctive constructs. Deliberately conservative: a wrong guess
+/// colours code misleadingly, no guess just leaves it plain.
+pub fn guess_language(code: &str) -> Option<&'static str> {
+    let has = |needle: &str| code.contains(needle);
+    let starts = |prefix: &str| {
+        code.lines()
+            .any(|line| line.trim_start().starts_with(prefix))
+    };
+    if starts("#include") {
+        Some("cpp")
+    } else if starts("fn ") || starts("pub fn ") || (has("let mut ") && has(";")) {
+        Some("rs")
+    } else if starts("def ") || (starts("import ") && !has(";")) || starts("from ") {
+        Some("py")
+    } else if starts("package ") && has("func ") {
+        Some("go")
+    } else if has("function ") || has("=> {") || starts("const ") || has("console.log") {
+        Some("js")
+    } else if starts("$ ") || starts("sudo ") {
+        Some("sh")
+    } else if starts("SELECT ") || starts("CREATE TABLE") || starts("INSERT INTO") {
+        Some("sql")
+    } else if code.trim_start().starts_with('<') && code.trim_end().ends_with('>') {
+        Some("html")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_hinted_and_guessed_languages() {
+        let html = highlight("let x = 1;\n", Some("rust")).unwrap();
+        assert!(html.contains("<span class=\"hl-source hl-rust\">"));
+        assert!(html.contains("hl-keyword"));
+
+        let guessed = highlight("def main():\n    print('<hi>')\n", None).unwrap();
+        assert!(guessed.contains("hl-python"));
+        assert!(guessed.contains("&lt;hi&gt;"));
+
+        assert_eq!(highlight("just some words\n", None), None);
+    }
+
+    #[test]
+    fn copies_class_hints_into_an_attribute() {
+        let html = r#"<pre class="x lang-py"><code class='language-rust'>a</code></pre><p class="language-go">"#;
+        assert_eq!(
+            tag_code_languages(html),
+            r#"<pre class="x lang-py" data-language="py"><code class='language-rust' data-language="rust">a</code></pre><p class="language-go">"#
+        );
+        assert_eq!(
+            tag_code_languages(r#"<code class="language-js"/><code class='lang-c' />"#),
+            r#"<code class="language-js" data-language="js"/><code class='lang-c' data-language="c"/>"#
+        );
+        assert_eq!(class_language("language-\"x"), None);
+    }
+
+    #[test]
+    fn stylesheets_style_the_prefixed_classes() {
+        assert!(DARK_STYLESHEET.contains(&format!(".{}", CODE_CLASS)));
+        assert!(LIGHT_STYLESHEET.contains(".hl-keyword"));
+    }
+}
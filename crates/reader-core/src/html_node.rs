@@ -33,8 +33,10 @@ const UNWRAP_SINGLE_CHILD: &[&str] = &[
     "amp-script",
 ];
 
-/// Elements allowed to exist without any children (void elements and images).
-const VOID_ELEMENTS: &[&str] = &["br", "hr", "img"];
+/// Elements allowed to exist without any children (void elements and
+/// images), plus table cells, whose place in their row matters even when
+/// empty.
+const VOID_ELEMENTS: &[&str] = &["br", "hr", "img", "td", "th"];
 
 /// Either a structural element or a raw text node. This is the tree fed
 /// into the `TextCompound` lowering step.
@@ -49,10 +51,12 @@ pub enum HTMLNode {
 }
 
 impl HTMLNode {
-    /// Recursively concatenate the text content of this subtree.
+    /// Recursively concatenate the text content of this subtree, with a
+    /// line break for each `<br>`.
     pub fn get_text(&self) -> String {
         fn walk(node: &HTMLNode, out: &mut String) {
             match node {
+                HTMLNode::Element { tag, .. } if tag == "br" => out.push('\n'),
                 HTMLNode::Element { children, .. } => children.iter().for_each(|c| walk(c, out)),
                 HTMLNode::Text(text) => out.push_str(text),
             }
@@ -63,10 +67,17 @@ impl HTMLNode {
     }
 
     pub fn from_handle(handle: &Handle) -> Result<HTMLNode, NodeError> {
+        Self::lower(handle, false)
+    }
+
+    /// `from_handle`, inside a `<pre>` when `preformatted`: there the
+    /// whitespace-only text between the spans of pre-highlighted code
+    /// carries its line breaks and indentation, so it is kept.
+    fn lower(handle: &Handle, preformatted: bool) -> Result<HTMLNode, NodeError> {
         // Text nodes short-circuit: no pruning rules apply.
         if let NodeData::Text { contents } = &handle.data {
             let text = contents.borrow();
-            return if text.trim().is_empty() {
+            return if text.trim().is_empty() && !preformatted {
                 Err(NodeError::EmptyText)
             } else {
                 Ok(Self::Text(text.to_string()))
@@ -93,14 +104,15 @@ impl HTMLNode {
             });
         }
 
+        let preformatted = preformatted || tag == "pre";
         let mut children: Vec<HTMLNode> = handle
             .children
             .borrow()
             .iter()
-            .flat_map(Self::from_handle)
+            .flat_map(|child| Self::lower(child, preformatted))
             .collect();
 
-        // Void elements are emitted as-is — even if empty.
+        // Void elements and cells are emitted as-is — even if empty.
         if VOID_ELEMENTS.contains(&tag) {
             return Ok(Self::Element {
                 tag: tag.to_owned(),
@@ -203,6 +215,12 @@ mod tests {
         assert_eq!(ts.iter().filter(|t| t.as_str() == "p").count(), 1);
     }
 
+    #[test]
+    fn keeps_line_structure_inside_pre() {
+        let node = parse("<pre><span>a</span>\n  <span>b</span><br><span>c</span></pre>");
+        assert_eq!(node.get_text(), "a\n  b\nc");
+    }
+
     #[test]
     fn preserves_heading_and_link() {
         let node = parse("<html><body><h1>Title</h1><a href=\"/x\">link</a></body></html>");
@@ -0,0 +1,127 @@
+//! Character-encoding sniffing for fetched articles, following the WHATWG
+//! HTML "determine the character encoding" steps in order:
+//!
+//! 1. a byte order mark,
+//! 2. the `charset` parameter of the `Content-Type` header,
+//! 3. a prescan of the first 1024 bytes for `<meta charset>` or
+//!    `<meta http-equiv="Content-Type" content="…; charset=…">`,
+//! 4. statistical detection over the whole body (chardetng), seeded with
+//!    the page's top-level domain.
+//!
+//! Labels are resolved and bytes decoded through encoding_rs, which
+//! implements the WHATWG Encoding Standard tables (so `iso-8859-1` is
+//! treated as windows-1252, as browsers do).
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+
+/// How far into the document the `<meta>` prescan looks.
+const PRESCAN_LIMIT: usize = 1024;
+
+static META_CHARSET: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)<meta\s[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.+-]+)"#).unwrap());
+
+/// Decode an HTML body. `content_type` is the response's `Content-Type`
+/// header, `tld` the last label of its host (`"jp"`, `"ru"`, …).
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>, tld: Option<&str>) -> String {
+    let encoding = sniff(bytes, content_type, tld);
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
+}
+
+/// Pick the encoding `bytes` should be decoded with.
+pub fn sniff(bytes: &[u8], content_type: Option<&str>, tld: Option<&str>) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if let Some(encoding) = content_type.and_then(header_charset) {
+        return encoding;
+    }
+    if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LIMIT)]) {
+        return encoding;
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(tld.map(str::as_bytes), true)
+}
+
+/// The `charset` parameter of a `Content-Type` value, if it names a
+/// known encoding.
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(
+            value
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'')
+                .as_bytes(),
+        )
+    })
+}
+
+/// `<meta>` prescan. A declared UTF-16 can't be true of a document we
+/// could read the declaration from as ASCII, so it means UTF-8; and
+/// `x-user-defined` means windows-1252.
+fn prescan(head: &[u8]) -> Option<&'static Encoding> {
+    let label = META_CHARSET.captures(head)?.get(1)?.as_bytes();
+    let encoding = Encoding::for_label(label)?;
+    Some(if encoding == UTF_16BE || encoding == UTF_16LE {
+        UTF_8
+    } else if encoding == X_USER_DEFINED {
+        WINDOWS_1252
+    } else {
+        encoding
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/charset/",
+                $name
+            ))
+        };
+    }
+
+    #[test]
+    fn meta_declared_fixtures_decode() {
+        let cases: [(&[u8], &str); 5] = [
+            (fixture!("shift_jis.html"), "日本語のテキスト"),
+            (fixture!("gb18030.html"), "中文文本"),
+            (fixture!("windows-1251.html"), "Русский текст"),
+            (fixture!("windows-1252.html"), "Café “déjà vu” – naïve"),
+            (fixture!("iso-8859-1-http-equiv.html"), "Ærøskøbing"),
+        ];
+        for (bytes, expected) in cases {
+            let text = decode_html(bytes, None, None);
+            assert!(text.contains(expected), "{:?} not in {:?}", expected, text);
+        }
+    }
+
+    #[test]
+    fn bom_and_header_win_over_meta() {
+        let text = decode_html(fixture!("utf-16le-bom.html"), None, None);
+        assert!(text.contains("Grüße"));
+        let bytes = fixture!("header-only-koi8-r.html");
+        assert_eq!(
+            sniff(bytes, Some("text/html; charset=KOI8-R"), None).name(),
+            "KOI8-R"
+        );
+        assert!(decode_html(bytes, Some("text/html; charset=\"koi8-r\""), None).contains("Привет"));
+    }
+
+    #[test]
+    fn undeclared_page_is_detected() {
+        let bytes = fixture!("undeclared-windows-1251.html");
+        assert_eq!(sniff(bytes, None, Some("ru")).name(), "windows-1251");
+    }
+}
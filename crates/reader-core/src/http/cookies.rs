@@ -0,0 +1,251 @@
+//! Cookie isolation between renders.
+//!
+//! Every render fetches through its own [`RenderJar`], so cookies set
+//! while fetching one article (consent state, A/B buckets, session ids)
+//! never reach another reader's fetch. Hosts under a domain listed in
+//! `identity.persistent_cookie_domains` are the exception: their cookies
+//! go to one process-wide jar that is written through to SQLite and
+//! reloaded at startup, so a site we deliberately log into stays logged
+//! in. Persisted cookies carry an absolute expiry, fixed when they were
+//! received, and are written from a thread of their own rather than from
+//! the fetch that received them.
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use reqwest::{
+    cookie::{CookieStore, Jar},
+    header::HeaderValue,
+    Url,
+};
+
+use super::domain_matches;
+use crate::{
+    cache::{self, StoredCookie},
+    config::CONFIG,
+};
+
+/// The last second `httpdate` can format (end of year 9999).
+const LATEST_EXPIRY: i64 = 253_402_300_799;
+
+/// The jar shared by persistent domains, seeded from SQLite.
+static PERSISTENT: Lazy<Jar> = Lazy::new(|| {
+    let jar = Jar::default();
+    match cache::load_cookies() {
+        Ok(cookies) => {
+            for (url, set_cookie) in cookies {
+                if let Ok(url) = Url::parse(&url) {
+                    jar.add_cookie_str(&set_cookie, &url);
+                }
+            }
+        }
+        Err(e) => eprintln!("loading persistent cookies: {}", e),
+    }
+    jar
+});
+
+/// Writes persistent cookies to SQLite in the order they arrived, so the
+/// global DB lock is never taken on the executor thread running a fetch.
+static WRITER: Lazy<mpsc::Sender<StoredCookie>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<StoredCookie>();
+    thread::Builder::new()
+        .name("cookie-writer".into())
+        .spawn(move || {
+            for cookie in receiver {
+                if let Err(e) = cache::store_cookie(&cookie) {
+                    eprintln!("persisting cookie for {}: {}", cookie.url, e);
+                }
+            }
+        })
+        .expect("spawn cookie writer");
+    sender
+});
+
+/// Whether cookies for `url` belong in the persistent jar.
+fn is_persistent(url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    CONFIG
+        .identity
+        .persistent_cookie_domains
+        .iter()
+        .any(|domain| domain_matches(host, domain))
+}
+
+/// Cookie store for a single render: ephemeral for most hosts, backed by
+/// the persistent jar for configured domains.
+#[derive(Default)]
+pub struct RenderJar {
+    ephemeral: Jar,
+}
+
+impl CookieStore for RenderJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        if !is_persistent(url) {
+            return self.ephemeral.set_cookies(cookie_headers, url);
+        }
+        let headers: Vec<&HeaderValue> = cookie_headers.collect();
+        for header in &headers {
+            let Ok(set_cookie) = header.to_str() else {
+                continue;
+            };
+            if let Some(cookie) = to_stored(set_cookie, url, SystemTime::now()) {
+                // The receiving end lives as long as the process.
+                let _ = WRITER.send(cookie);
+            }
+        }
+        PERSISTENT.set_cookies(&mut headers.into_iter(), url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        if is_persistent(url) {
+            PERSISTENT.cookies(url)
+        } else {
+            self.ephemeral.cookies(url)
+        }
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// The path a cookie without a `Path` attribute is scoped to: the request
+/// path up to its last `/` (RFC 6265, section 5.1.4).
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(end) => url.path()[..end].to_owned(),
+    }
+}
+
+/// The row persisting `set_cookie`, received from `url` at `now`. Its
+/// `Max-Age` (which wins over `Expires`) is turned into an absolute
+/// `Expires`, so the stored header means the same whenever it is
+/// replayed. `None` for a header without a name or with a `Domain` that
+/// `url` cannot set.
+fn to_stored(set_cookie: &str, url: &Url, now: SystemTime) -> Option<StoredCookie> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let mut parts = set_cookie.split(';');
+    let pair = parts.next()?.trim();
+    let name = pair.split_once('=')?.0.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut kept = vec![pair];
+    let (mut domain, mut path, mut max_age, mut expires) = (None, None, None, None);
+    for attribute in parts.map(str::trim).filter(|a| !a.is_empty()) {
+        let (key, value) = attribute
+            .split_once('=')
+            .map_or((attribute, ""), |(key, value)| (key.trim(), value.trim()));
+        match key.to_ascii_lowercase().as_str() {
+            "max-age" => {
+                max_age = value.parse::<i64>().ok();
+                continue;
+            }
+            "expires" => {
+                expires = httpdate::parse_http_date(value).ok();
+                continue;
+            }
+            "domain" => {
+                let value = value.trim_start_matches('.').to_ascii_lowercase();
+                if !value.is_empty() {
+                    domain = Some(value);
+                }
+            }
+            "path" if value.starts_with('/') => path = Some(value.to_owned()),
+            _ => {}
+        }
+        kept.push(attribute);
+    }
+    if domain.as_deref().is_some_and(|d| !domain_matches(&host, d)) {
+        return None;
+    }
+
+    let expires_at = match (max_age, expires) {
+        (Some(seconds), _) => Some(unix_seconds(now).saturating_add(seconds)),
+        (None, Some(at)) => Some(unix_seconds(at)),
+        (None, None) => None,
+    }
+    .map(|at| at.clamp(0, LATEST_EXPIRY));
+    let expiry = expires_at.map(|at| {
+        let at = UNIX_EPOCH + Duration::from_secs(at as u64);
+        format!("Expires={}", httpdate::fmt_http_date(at))
+    });
+
+    Some(StoredCookie {
+        domain: domain.unwrap_or(host),
+        path: path.unwrap_or_else(|| default_path(url)),
+        name: name.to_owned(),
+        url: url.to_string(),
+        set_cookie: kept
+            .into_iter()
+            .chain(expiry.as_deref())
+            .collect::<Vec<_>>()
+            .join("; "),
+        expires_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received(set_cookie: &str, url: &str) -> Option<StoredCookie> {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        to_stored(set_cookie, &Url::parse(url).unwrap(), now)
+    }
+
+    #[test]
+    fn max_age_becomes_an_absolute_expiry() {
+        let cookie = received(
+            "sid=abc; Max-Age=3600; Expires=Thu, 01 Jan 2099 00:00:00 GMT; HttpOnly",
+            "https://example.com/account/login",
+        )
+        .unwrap();
+        assert_eq!(
+            cookie.set_cookie,
+            "sid=abc; HttpOnly; Expires=Wed, 21 Oct 2015 08:28:00 GMT"
+        );
+        assert_eq!(cookie.expires_at, Some(1_445_416_080));
+
+        let session = received("sid=abc", "https://example.com/").unwrap();
+        assert_eq!(session.set_cookie, "sid=abc");
+        assert_eq!(session.expires_at, None);
+    }
+
+    #[test]
+    fn cookies_are_keyed_by_domain_and_path() {
+        let scoped = received(
+            "sid=abc; Domain=.Example.com; Path=/app",
+            "https://www.example.com/app/login",
+        )
+        .unwrap();
+        assert_eq!(
+            (
+                scoped.domain.as_str(),
+                scoped.path.as_str(),
+                scoped.name.as_str()
+            ),
+            ("example.com", "/app", "sid")
+        );
+
+        let defaulted = received("sid=abc", "https://www.example.com/app/login").unwrap();
+        assert_eq!(
+            (defaulted.domain.as_str(), defaulted.path.as_str()),
+            ("www.example.com", "/app")
+        );
+        assert_eq!(
+            received("sid=abc", "https://example.com/").unwrap().path,
+            "/"
+        );
+
+        assert!(received("sid=abc; Domain=other.com", "https://example.com/").is_none());
+    }
+}
@@ -0,0 +1,271 @@
+//! HTTP fetch helpers — per-render [`Session`]s for articles plus the
+//! `http_get` / `http_get_bytes` entry points used by the pipeline and
+//! the image worker respectively.
+//!
+//! Bodies are read chunk by chunk and abandoned as soon as they cross
+//! their size limit, so a response without a `Content-Length` can't
+//! exhaust memory. Each fetch is bounded by the configured connect,
+//! read (idle between chunks) and total timeouts, retried when it fails
+//! transiently ([`retry`]), and waits its turn behind other fetches to
+//! the same host ([`host_limit`]).
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    StatusCode, Url,
+};
+
+use super::{
+    charset,
+    cookies::RenderJar,
+    guard::{self, GuardedResolver},
+    host_limit, identity,
+    retry::{self, retrying},
+};
+use crate::{config::CONFIG, http_error::HttpError};
+
+/// Hard ceiling on image fetch size — 50 MB. Independent of
+/// `CONFIG.max_size` (which applies to articles) because a large hero
+/// image is sometimes larger than the entire article body we expect.
+const IMAGE_SIZE_LIMIT: u64 = 50_000_000;
+
+/// Content negotiation headers every request carries. Identity headers
+/// (`User-Agent`, `Accept-Language`, extras) depend on the host and are
+/// added per request by [`identity::headers_for`].
+fn default_headers() -> HeaderMap {
+    let mut h = HeaderMap::new();
+    h.insert(
+        header::ACCEPT_ENCODING,
+        HeaderValue::from_static("gzip, deflate"),
+    );
+    h.insert(
+        header::ACCEPT,
+        HeaderValue::from_static(
+            "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.9",
+        ),
+    );
+    h
+}
+
+/// Every client shares this configuration, including the fetch guard
+/// and the connect and total timeouts. No client shares a cookie store:
+/// article fetches get one per [`Session`], image fetches none at all.
+fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .default_headers(default_headers())
+        .dns_resolver(Arc::new(GuardedResolver))
+        .redirect(guard::redirect_policy())
+        .connect_timeout(Duration::from_secs(CONFIG.fetch_connect_timeout))
+        .timeout(Duration::from_secs(CONFIG.fetch_total_timeout))
+}
+
+thread_local! {
+    /// Image fetches run on the image worker threads (and the blocking
+    /// render path), outside any async context. Each such thread drives
+    /// its own single-threaded runtime, with a client whose connection
+    /// pool lives on that runtime.
+    static IMAGE_FETCHER: (tokio::runtime::Runtime, reqwest::Client) = (
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build image fetch runtime"),
+        client_builder()
+            .build()
+            .expect("failed to build image fetch client"),
+    );
+}
+
+/// Parse `url` and run the guard's IP-literal check before any request
+/// goes out; hostnames are vetted later, at resolution time.
+fn guarded_url(url: &str) -> Result<Url, HttpError> {
+    let url = Url::parse(url).map_err(|_| HttpError::InvalidUrl(url.to_owned()))?;
+    guard::check_url(&url)?;
+    Ok(url)
+}
+
+/// Blocking image fetch — called from the std::thread image-actor worker.
+/// Must not be called from inside an async task.
+pub fn http_get_bytes(url: &str) -> Result<Vec<u8>, HttpError> {
+    let url = guarded_url(url)?;
+    let headers = identity::headers_for(&url, None);
+    let (url, headers) = (&url, &headers);
+    IMAGE_FETCHER.with(|(runtime, client)| {
+        runtime.block_on(retrying(|| async move {
+            let _permit = host_limit::acquire(url).await;
+            let resp = send(client.get(url.clone()).headers(headers.clone())).await?;
+            read_limited(resp, IMAGE_SIZE_LIMIT).await
+        }))
+    })
+}
+
+/// Send `request`, turning a response worth retrying into
+/// [`HttpError::Unavailable`].
+async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, HttpError> {
+    let resp = within_read_timeout(request.send()).await?;
+    if retry::is_retryable_status(resp.status()) {
+        return Err(retry::unavailable(&resp));
+    }
+    Ok(resp)
+}
+
+/// Await one network step (response headers or a body chunk), failing
+/// with [`HttpError::ReadTimeout`] if the peer stays silent too long.
+async fn within_read_timeout<T>(
+    step: impl Future<Output = Result<T, reqwest::Error>>,
+) -> Result<T, HttpError> {
+    let secs = CONFIG.fetch_read_timeout;
+    tokio::time::timeout(Duration::from_secs(secs), step)
+        .await
+        .map_err(|_| HttpError::ReadTimeout { secs })?
+        .map_err(HttpError::from)
+}
+
+/// Read a response body chunk by chunk, giving up as soon as it exceeds
+/// `limit` bytes.
+async fn read_limited(mut resp: reqwest::Response, limit: u64) -> Result<Vec<u8>, HttpError> {
+    if resp.content_length().unwrap_or(0) > limit {
+        return Err(HttpError::TooLarge { limit });
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = within_read_timeout(resp.chunk()).await? {
+        if (body.len() + chunk.len()) as u64 > limit {
+            return Err(HttpError::TooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Cache validators from an origin response, echoed back as
+/// `If-None-Match` / `If-Modified-Since` to revalidate a stored render.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        Self {
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
+    }
+
+    /// Whether the origin gave us anything to revalidate with.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Per-request variations on an article fetch.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FetchOptions {
+    /// Validators of a cached render; makes the fetch conditional.
+    pub revalidate: Option<Validators>,
+    /// The reader's own `Accept-Language`, sent instead of the
+    /// configured one.
+    pub accept_language: Option<String>,
+}
+
+/// An article body together with the status and validators of the
+/// response it came from and the URL it was finally served from, after
+/// redirects.
+pub struct FetchedPage {
+    pub status: StatusCode,
+    pub body: String,
+    pub validators: Validators,
+    pub url: Url,
+}
+
+/// One-off async article fetch in a fresh [`Session`].
+pub async fn http_get(url: &str) -> Result<String, HttpError> {
+    let page = Session::new()?
+        .get_page(url, &FetchOptions::default())
+        .await?;
+    Ok(page.map(|page| page.body).unwrap_or_default())
+}
+
+/// The fetches made for one render. Cookies set during the session stay
+/// in its own [`RenderJar`] and are dropped with it, apart from those of
+/// persistent domains.
+pub struct Session {
+    client: reqwest::Client,
+}
+
+impl Session {
+    pub fn new() -> Result<Self, HttpError> {
+        let client = client_builder()
+            .cookie_provider(Arc::new(RenderJar::default()))
+            .build()?;
+        Ok(Self { client })
+    }
+
+    /// Fetch an article, conditionally if `options.revalidate` is given.
+    /// Returns `None` when the origin answers `304 Not Modified`.
+    ///
+    /// The body is decoded with the encoding [`charset::sniff`] settles on
+    /// from its BOM, `Content-Type` header, `<meta>` declaration or content.
+    pub async fn get_page(
+        &self,
+        url: &str,
+        options: &FetchOptions,
+    ) -> Result<Option<FetchedPage>, HttpError> {
+        get_page(&self.client, url, options).await
+    }
+}
+
+async fn get_page(
+    client: &reqwest::Client,
+    url: &str,
+    options: &FetchOptions,
+) -> Result<Option<FetchedPage>, HttpError> {
+    let url = guarded_url(url)?;
+    let mut headers = identity::headers_for(&url, options.accept_language.as_deref());
+    let validators = options.revalidate.as_ref();
+    if let Some(validators) = validators {
+        if let Some(etag) = validators.etag.as_deref().and_then(header_value) {
+            headers.insert(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators.last_modified.as_deref().and_then(header_value) {
+            headers.insert(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let (url, headers) = (&url, &headers);
+    retrying(|| async move {
+        let _permit = host_limit::acquire(url).await;
+        let resp = send(client.get(url.clone()).headers(headers.clone())).await?;
+        if validators.is_some() && resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let status = resp.status();
+        let validators = Validators::from_headers(resp.headers());
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let url = resp.url().clone();
+        let tld = url.host_str().and_then(|host| host.rsplit('.').next());
+        let bytes = read_limited(resp, CONFIG.max_size).await?;
+        let body = charset::decode_html(&bytes, content_type.as_deref(), tld);
+        Ok(Some(FetchedPage {
+            status,
+            body,
+            validators,
+            url,
+        }))
+    })
+    .await
+}
+
+fn header_value(value: &str) -> Option<HeaderValue> {
+    HeaderValue::from_str(value).ok()
+}
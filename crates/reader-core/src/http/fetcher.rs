@@ -0,0 +1,71 @@
+//! The [`Fetcher`] abstraction every download of a render goes through:
+//! the article, its AMP version and its images. The server renders with
+//! [`NetworkFetcher`]; tests hand the pipeline a
+//! [`super::fixture::FixtureFetcher`] instead and render recorded pages
+//! without touching the network.
+
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use super::fetch::{http_get_bytes, FetchOptions, FetchedPage, Session};
+use crate::{config::CONFIG, http_error::HttpError};
+
+/// Boxed future returned by [`FetchSession::get_page`], so the trait
+/// stays object safe.
+pub type FetchFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, HttpError>> + Send + 'a>>;
+
+/// Source of the bytes a render needs.
+pub trait Fetcher: Send + Sync {
+    /// Start the page fetches of one render. Pages fetched through one
+    /// session share cookies.
+    fn session(&self) -> Result<Box<dyn FetchSession>, HttpError>;
+
+    /// Download an image. Blocks, so it must be called off the async
+    /// executor (image workers, `spawn_blocking`).
+    fn get_bytes(&self, url: &str) -> Result<Vec<u8>, HttpError>;
+
+    /// Where re-encodes of the images downloaded through this fetcher are
+    /// cached: the image cache `/i/` serves from, unless the bytes don't
+    /// come from the live web.
+    fn image_cache_dir(&self) -> PathBuf {
+        Path::new(&CONFIG.cache_folder).join("images")
+    }
+}
+
+/// Page fetches made for a single render.
+pub trait FetchSession: Send + Sync {
+    /// Fetch an article, conditionally if `options.revalidate` is given.
+    /// Returns `None` when the page is unchanged since those validators.
+    fn get_page<'a>(
+        &'a self,
+        url: &'a str,
+        options: &'a FetchOptions,
+    ) -> FetchFuture<'a, Option<FetchedPage>>;
+}
+
+/// Fetches from the live web through the guarded reqwest clients.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetworkFetcher;
+
+impl Fetcher for NetworkFetcher {
+    fn session(&self) -> Result<Box<dyn FetchSession>, HttpError> {
+        Ok(Box::new(Session::new()?))
+    }
+
+    fn get_bytes(&self, url: &str) -> Result<Vec<u8>, HttpError> {
+        http_get_bytes(url)
+    }
+}
+
+impl FetchSession for Session {
+    fn get_page<'a>(
+        &'a self,
+        url: &'a str,
+        options: &'a FetchOptions,
+    ) -> FetchFuture<'a, Option<FetchedPage>> {
+        Box::pin(Session::get_page(self, url, options))
+    }
+}
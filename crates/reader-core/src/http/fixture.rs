@@ -0,0 +1,299 @@
+//! [`FixtureFetcher`]: a [`Fetcher`] that answers from recorded
+//! responses instead of the network, for deterministic end-to-end tests.
+//!
+//! A fixture directory holds one file per response body plus an
+//! `exchanges.toml` manifest describing them. Bundles written by
+//! [`super::record::RecordingFetcher`] also name the article they render
+//! and when they were recorded:
+//!
+//! ```toml
+//! article = "http://example.com/post"      # optional
+//! recorded = "Sun, 18 Oct 2026 09:00:00 GMT" # optional
+//! license = "CC BY-SA 4.0"                  # optional
+//!
+//! [[exchange]]
+//! url = "http://example.com/post"
+//! final_url = "https://example.com/post"   # after redirects, optional
+//! status = 404                              # when not 200, optional
+//! content_type = "text/html; charset=utf-8" # optional
+//! etag = "\"v1\""                           # optional
+//! last_modified = "…"                       # optional
+//! file = "post.html"
+//! ```
+//!
+//! A URL with no recorded exchange fails with [`HttpError::NotRecorded`].
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    charset,
+    fetch::{FetchOptions, FetchedPage, Validators},
+    fetcher::{FetchFuture, FetchSession, Fetcher},
+};
+use crate::{config::CONFIG, http_error::HttpError};
+
+/// Name of the manifest inside a fixture directory.
+pub const MANIFEST: &str = "exchanges.toml";
+
+/// One manifest entry: a request URL and the response recorded for it.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExchangeEntry {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Body file, relative to the fixture directory.
+    pub file: String,
+}
+
+/// `exchanges.toml` as a whole.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    /// URL of the article the bundle was recorded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub article: Option<String>,
+    /// When the bundle was recorded, as an HTTP date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recorded: Option<String>,
+    /// Terms the recorded pages may be redistributed under. Not known to
+    /// the recorder; filled in by hand before a bundle is committed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, rename = "exchange")]
+    pub exchanges: Vec<ExchangeEntry>,
+}
+
+/// A recorded response.
+#[derive(Clone, Debug, Default)]
+pub struct Exchange {
+    /// Where the response was finally served from; the request URL when
+    /// `None`.
+    pub final_url: Option<String>,
+    pub status: StatusCode,
+    pub content_type: Option<String>,
+    pub validators: Validators,
+    pub body: Vec<u8>,
+}
+
+/// Serves recorded responses keyed by request URL.
+#[derive(Clone, Debug, Default)]
+pub struct FixtureFetcher {
+    article: Option<String>,
+    exchanges: Arc<HashMap<String, Exchange>>,
+}
+
+impl FixtureFetcher {
+    pub fn new(exchanges: HashMap<String, Exchange>) -> Self {
+        Self {
+            article: None,
+            exchanges: Arc::new(exchanges),
+        }
+    }
+
+    /// The article a loaded bundle was recorded from, if it says.
+    pub fn article(&self) -> Option<&str> {
+        self.article.as_deref()
+    }
+
+    /// Load the fixture directory at `dir`.
+    pub fn from_dir(dir: &Path) -> Result<Self, HttpError> {
+        let fixture_error = |e: &dyn std::fmt::Display| HttpError::Fixture {
+            path: dir.display().to_string(),
+            reason: e.to_string(),
+        };
+        let manifest =
+            std::fs::read_to_string(dir.join(MANIFEST)).map_err(|e| fixture_error(&e))?;
+        let manifest: Manifest = toml::from_str(&manifest).map_err(|e| fixture_error(&e))?;
+        let mut exchanges = HashMap::new();
+        for entry in manifest.exchanges {
+            let body = std::fs::read(dir.join(&entry.file)).map_err(|e| fixture_error(&e))?;
+            exchanges.insert(
+                entry.url,
+                Exchange {
+                    final_url: entry.final_url,
+                    status: match entry.status {
+                        Some(status) => {
+                            StatusCode::from_u16(status).map_err(|e| fixture_error(&e))?
+                        }
+                        None => StatusCode::OK,
+                    },
+                    content_type: entry.content_type,
+                    validators: Validators {
+                        etag: entry.etag,
+                        last_modified: entry.last_modified,
+                    },
+                    body,
+                },
+            );
+        }
+        Ok(Self {
+            article: manifest.article,
+            exchanges: Arc::new(exchanges),
+        })
+    }
+
+    fn exchange(&self, url: &str) -> Result<&Exchange, HttpError> {
+        self.exchanges
+            .get(url)
+            .ok_or_else(|| HttpError::NotRecorded(url.to_owned()))
+    }
+
+    /// Answer a page fetch the way the network session would: decoded
+    /// body, final URL, and `None` when the request's validators match.
+    fn get_page(
+        &self,
+        url: &str,
+        options: &FetchOptions,
+    ) -> Result<Option<FetchedPage>, HttpError> {
+        let exchange = self.exchange(url)?;
+        if options
+            .revalidate
+            .as_ref()
+            .is_some_and(|v| !v.is_empty() && *v == exchange.validators)
+        {
+            return Ok(None);
+        }
+        let final_url = exchange.final_url.as_deref().unwrap_or(url);
+        let final_url =
+            Url::parse(final_url).map_err(|_| HttpError::InvalidUrl(final_url.to_owned()))?;
+        let tld = final_url
+            .host_str()
+            .and_then(|host| host.rsplit('.').next());
+        let body = charset::decode_html(&exchange.body, exchange.content_type.as_deref(), tld);
+        Ok(Some(FetchedPage {
+            status: exchange.status,
+            body,
+            validators: exchange.validators.clone(),
+            url: final_url,
+        }))
+    }
+}
+
+impl Fetcher for FixtureFetcher {
+    fn session(&self) -> Result<Box<dyn FetchSession>, HttpError> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn get_bytes(&self, url: &str) -> Result<Vec<u8>, HttpError> {
+        Ok(self.exchange(url)?.body.clone())
+    }
+
+    /// Re-encodes of recorded images never mix with the live cache.
+    fn image_cache_dir(&self) -> PathBuf {
+        Path::new(&CONFIG.cache_folder).join("replay_images")
+    }
+}
+
+impl FetchSession for FixtureFetcher {
+    fn get_page<'a>(
+        &'a self,
+        url: &'a str,
+        options: &'a FetchOptions,
+    ) -> FetchFuture<'a, Option<FetchedPage>> {
+        let page = FixtureFetcher::get_page(self, url, options);
+        Box::pin(async move { page })
+    }
+}
+
+/// A uniquely named directory under the system temp dir for a test to
+/// write files into, removed with everything in it when dropped.
+#[cfg(test)]
+pub(crate) struct ScratchDir(pub std::path::PathBuf);
+
+#[cfg(test)]
+impl ScratchDir {
+    pub(crate) fn new(prefix: &str) -> Self {
+        let name = format!(
+            "{}_{}_{:016x}",
+            prefix,
+            std::process::id(),
+            rand::random::<u64>()
+        );
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).expect("create scratch dir");
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetcher() -> FixtureFetcher {
+        let page = Exchange {
+            final_url: Some("https://example.com/post".into()),
+            content_type: Some("text/html; charset=windows-1252".into()),
+            validators: Validators {
+                etag: Some("\"v1\"".into()),
+                last_modified: None,
+            },
+            body: b"<p>caf\xe9</p>".to_vec(),
+            ..Default::default()
+        };
+        FixtureFetcher::new([("http://example.com/post".to_owned(), page)].into())
+    }
+
+    #[test]
+    fn serves_decoded_page_at_its_final_url() {
+        let page = fetcher()
+            .get_page("http://example.com/post", &FetchOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.body, "<p>café</p>");
+        assert_eq!(page.url.as_str(), "https://example.com/post");
+        let unchanged = FetchOptions {
+            revalidate: Some(page.validators),
+            ..Default::default()
+        };
+        assert!(fetcher()
+            .get_page("http://example.com/post", &unchanged)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn unrecorded_url_is_an_error() {
+        assert!(matches!(
+            fetcher().get_bytes("https://example.com/missing.png"),
+            Err(HttpError::NotRecorded(url)) if url.ends_with("missing.png")
+        ));
+    }
+
+    #[test]
+    fn loads_fixture_directory() {
+        let scratch = ScratchDir::new("clean_reader_fixture_dir");
+        let dir = &scratch.0;
+        std::fs::write(dir.join("a.png"), b"png").unwrap();
+        std::fs::write(
+            dir.join(MANIFEST),
+            "[[exchange]]\nurl = \"https://example.com/a.png\"\nfile = \"a.png\"\n",
+        )
+        .unwrap();
+        let fetcher = FixtureFetcher::from_dir(dir).unwrap();
+        assert_eq!(
+            fetcher.get_bytes("https://example.com/a.png").unwrap(),
+            b"png"
+        );
+    }
+}
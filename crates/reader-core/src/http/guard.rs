@@ -0,0 +1,266 @@
+//! Server-side request forgery guard.
+//!
+//! Anyone who can reach `/r/{…}` can make this server fetch a URL, so
+//! every outbound request is vetted at two points:
+//!
+//! - [`GuardedResolver`] filters DNS answers, so a hostname that resolves
+//!   to a loopback, link-local, private or denylisted address can't be
+//!   connected to — including names that only resolve that way on a
+//!   later lookup.
+//! - [`check_url`] covers IP-literal URLs, which never reach the
+//!   resolver, and runs on the initial URL and on every redirect hop via
+//!   [`redirect_policy`].
+//!
+//! `fetch_allowlist` in the config exempts hosts or ranges (an intranet
+//! wiki, say) from the built-in ranges; `fetch_denylist` adds ranges.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use hyper::client::connect::dns::Name;
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect, Url,
+};
+
+use crate::{config::CONFIG, http_error::HttpError};
+
+/// Same hop limit as reqwest's default policy.
+const MAX_REDIRECTS: usize = 10;
+
+/// Ranges no fetch may reach unless allowlisted: "this network",
+/// loopback, RFC 1918 private, carrier-grade NAT, link-local (cloud
+/// metadata lives at 169.254.169.254), benchmarking, multicast and
+/// reserved space, plus their IPv6 counterparts and local-use NAT64.
+/// IPv6 addresses that embed an IPv4 one are judged by it, see
+/// [`embedded_ipv4`].
+const BLOCKED_RANGES: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "64:ff9b:1::/48",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+/// Which hosts and addresses outbound fetches may reach.
+pub struct Policy {
+    allowed_hosts: Vec<String>,
+    allowed_nets: Vec<IpNet>,
+    blocked_nets: Vec<IpNet>,
+}
+
+// The config was checked with `Policy::new` when it was loaded.
+static POLICY: Lazy<Policy> = Lazy::new(|| {
+    Policy::new(&CONFIG.fetch_allowlist, &CONFIG.fetch_denylist).expect("validated config")
+});
+
+impl Policy {
+    /// Build a policy from allowlist entries (hostnames, IPs or CIDR
+    /// ranges) and extra denylisted IPs or CIDR ranges. An invalid
+    /// denylist entry is an error: silently ignoring a typo would leave a
+    /// hole open.
+    pub fn new(allowlist: &[String], denylist: &[String]) -> Result<Self, String> {
+        let (allowed_nets, allowed_hosts): (Vec<_>, Vec<_>) = allowlist
+            .iter()
+            .map(|entry| parse_net(entry).ok_or_else(|| entry.to_ascii_lowercase()))
+            .partition(Result::is_ok);
+        let blocked_nets = BLOCKED_RANGES
+            .iter()
+            .map(|range| range.to_string())
+            .chain(denylist.iter().cloned())
+            .map(|range| {
+                parse_net(&range).ok_or_else(|| format!("invalid fetch_denylist range {range:?}"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            allowed_hosts: allowed_hosts.into_iter().filter_map(Result::err).collect(),
+            allowed_nets: allowed_nets.into_iter().filter_map(Result::ok).collect(),
+            blocked_nets,
+        })
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.allowed_hosts.contains(&host)
+    }
+
+    /// Reject `ip` if it falls in a blocked range and no allowlist entry
+    /// covers it. IPv6 addresses embedding an IPv4 one are judged as it.
+    pub fn check_ip(&self, host: &str, ip: IpAddr) -> Result<(), HttpError> {
+        let ip = match ip {
+            IpAddr::V6(v6) => embedded_ipv4(v6).map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        let blocked = self.blocked_nets.iter().any(|net| net.contains(&ip))
+            && !self.allowed_nets.iter().any(|net| net.contains(&ip));
+        if blocked {
+            Err(HttpError::BlockedAddress {
+                host: host.to_owned(),
+                ip,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Vet a URL before it is requested. Only IP-literal hosts can be
+    /// judged here; hostnames are checked by [`GuardedResolver`].
+    pub fn check_url(&self, url: &Url) -> Result<(), HttpError> {
+        let host = url.host_str().unwrap_or_default();
+        let ip = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+            Ok(ip) => ip,
+            Err(_) => return Ok(()),
+        };
+        if self.host_allowed(host) {
+            return Ok(());
+        }
+        self.check_ip(host, ip)
+    }
+}
+
+/// The IPv4 address an IPv6 one stands for: IPv4-mapped
+/// (`::ffff:0:0/96`), IPv4-compatible (`::/96`, but not `::` and `::1`
+/// themselves), NAT64 (`64:ff9b::/96`) or 6to4 (`2002::/16`).
+fn embedded_ipv4(v6: Ipv6Addr) -> Option<Ipv4Addr> {
+    let [a, b, c, d, e, f, g, h] = v6.segments();
+    let from = |high: u16, low: u16| Some(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+    match [a, b, c, d, e, f] {
+        _ if v6.is_loopback() || v6.is_unspecified() => None,
+        [0, 0, 0, 0, 0, 0xffff] | [0, 0, 0, 0, 0, 0] | [0x64, 0xff9b, 0, 0, 0, 0] => from(g, h),
+        [0x2002, ..] => from(b, c),
+        _ => None,
+    }
+}
+
+fn parse_net(entry: &str) -> Option<IpNet> {
+    entry
+        .parse::<IpNet>()
+        .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+        .ok()
+}
+
+/// [`Policy::check_url`] against the configured policy.
+pub fn check_url(url: &Url) -> Result<(), HttpError> {
+    POLICY.check_url(url)
+}
+
+/// Redirect policy that re-vets every hop.
+pub fn redirect_policy() -> redirect::Policy {
+    redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match check_url(attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(e) => attempt.error(e),
+        }
+    })
+}
+
+/// DNS resolver that drops blocked addresses from every answer and
+/// fails the lookup if nothing is left.
+pub struct GuardedResolver;
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_owned();
+            let resolved: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if POLICY.host_allowed(&host) {
+                return Ok(Box::new(resolved.into_iter()) as Addrs);
+            }
+            let mut rejection = None;
+            let allowed: Vec<SocketAddr> = resolved
+                .into_iter()
+                .filter(|addr| match POLICY.check_ip(&host, addr.ip()) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        rejection.get_or_insert(e);
+                        false
+                    }
+                })
+                .collect();
+            match rejection {
+                Some(e) if allowed.is_empty() => Err(Box::new(e) as _),
+                _ => Ok(Box::new(allowed.into_iter()) as Addrs),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> Policy {
+        let owned = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        Policy::new(&owned(allow), &owned(deny)).unwrap()
+    }
+
+    fn url(raw: &str) -> Url {
+        Url::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn blocks_loopback_private_and_metadata_literals() {
+        let p = policy(&[], &[]);
+        for raw in [
+            "http://127.0.0.1/",
+            "http://10.1.2.3/",
+            "http://192.168.0.10:8080/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://[::127.0.0.1]/",
+            "http://[64:ff9b::a9fe:a9fe]/",
+            "http://[2002:c0a8:1::1]/",
+            "http://198.18.0.1/",
+            "http://[fd00::1]/",
+        ] {
+            assert!(
+                matches!(
+                    p.check_url(&url(raw)),
+                    Err(HttpError::BlockedAddress { .. })
+                ),
+                "{raw} should be blocked"
+            );
+        }
+    }
+
+    #[test]
+    fn allows_public_addresses_and_hostnames() {
+        let p = policy(&[], &[]);
+        assert!(p.check_url(&url("http://93.184.216.34/")).is_ok());
+        assert!(p.check_url(&url("https://example.com/")).is_ok());
+        assert!(p.check_url(&url("http://[64:ff9b::5db8:d822]/")).is_ok());
+        assert!(p.check_url(&url("http://[2606:4700::1]/")).is_ok());
+    }
+
+    #[test]
+    fn invalid_denylist_entries_are_rejected() {
+        let deny = ["10.0.0.0/33".to_owned()];
+        assert!(Policy::new(&[], &deny).is_err());
+    }
+
+    #[test]
+    fn allowlist_exempts_ranges_and_denylist_adds_them() {
+        let p = policy(&["10.0.0.0/24", "wiki.lan"], &["93.184.216.0/24"]);
+        assert!(p.check_ip("wiki", "10.0.0.7".parse().unwrap()).is_ok());
+        assert!(p.check_ip("other", "10.0.1.7".parse().unwrap()).is_err());
+        assert!(p.check_ip("x", "93.184.216.34".parse().unwrap()).is_err());
+        assert!(p.host_allowed("WIKI.lan."));
+    }
+}
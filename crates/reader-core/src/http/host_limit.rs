@@ -0,0 +1,64 @@
+//! Per-host concurrency limit shared by article and image fetches, so a
+//! burst of images from one article can't hammer a single CDN. Each host
+//! gets a semaphore of `CONFIG.fetch_host_concurrency` permits (0 means
+//! unlimited); a fetch holds one from sending its request until its body
+//! has been read.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::CONFIG;
+
+static HOSTS: Lazy<Mutex<HashMap<String, Arc<Semaphore>>>> = Lazy::new(Mutex::default);
+
+/// Wait for a free slot on `url`'s host. `None` when the limit is off.
+pub(super) async fn acquire(url: &Url) -> Option<OwnedSemaphorePermit> {
+    let limit = CONFIG.fetch_host_concurrency;
+    if limit == 0 {
+        return None;
+    }
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let semaphore = host_semaphore(&HOSTS, host, limit)?;
+    semaphore.acquire_owned().await.ok()
+}
+
+/// The semaphore for `host`, created on first use. Semaphores of hosts
+/// nobody is fetching from are dropped along the way so the map doesn't
+/// grow with every host ever visited.
+fn host_semaphore(
+    hosts: &Mutex<HashMap<String, Arc<Semaphore>>>,
+    host: String,
+    limit: usize,
+) -> Option<Arc<Semaphore>> {
+    let mut hosts = hosts.lock().ok()?;
+    hosts.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+    Some(Arc::clone(
+        hosts
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(limit))),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_share_a_semaphore_until_idle() {
+        let hosts = Mutex::default();
+        let a = host_semaphore(&hosts, "a.example".into(), 2).unwrap();
+        let permit = Arc::clone(&a).try_acquire_owned().unwrap();
+        let again = host_semaphore(&hosts, "a.example".into(), 2).unwrap();
+        assert!(Arc::ptr_eq(&a, &again));
+        assert_eq!(again.available_permits(), 1);
+        drop((a, again, permit));
+        host_semaphore(&hosts, "b.example".into(), 2).unwrap();
+        assert!(!hosts.lock().unwrap().contains_key("a.example"));
+    }
+}
@@ -0,0 +1,213 @@
+//! Per-request identity headers (`User-Agent`, `Accept-Language` and
+//! configured extras), resolved from the `[identity]` config for the
+//! host being fetched.
+
+use once_cell::sync::Lazy;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Url,
+};
+
+use super::domain_matches;
+use crate::config::{DomainIdentity, Identity, CONFIG};
+
+/// `[identity]` parsed into header maps once. The config was checked by
+/// [`validate`] when it was loaded, so a bad header name or value stops
+/// the server at startup instead of failing a fetch.
+struct Resolved {
+    base: HeaderMap,
+    /// Domain suffix and the headers it overrides, longest suffix first.
+    domains: Vec<(String, HeaderMap)>,
+}
+
+static RESOLVED: Lazy<Resolved> =
+    Lazy::new(|| Resolved::new(&CONFIG.identity).expect("validated config"));
+
+/// Most language ranges kept from a reader's `Accept-Language`.
+const MAX_READER_LANGUAGES: usize = 3;
+
+impl Resolved {
+    fn new(identity: &Identity) -> Result<Self, String> {
+        let mut base = HeaderMap::new();
+        insert(&mut base, header::USER_AGENT.as_str(), &identity.user_agent)?;
+        insert(
+            &mut base,
+            header::ACCEPT_LANGUAGE.as_str(),
+            &identity.accept_language,
+        )?;
+        for (name, value) in &identity.headers {
+            insert(&mut base, name, value)?;
+        }
+        let mut domains = identity
+            .domains
+            .iter()
+            .map(|(domain, overrides)| {
+                Ok((
+                    domain.trim_start_matches('.').to_owned(),
+                    domain_headers(overrides)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        domains.sort_by_key(|(domain, _)| std::cmp::Reverse(domain.len()));
+        Ok(Self { base, domains })
+    }
+
+    fn headers_for(&self, host: &str, accept_language: Option<&str>) -> HeaderMap {
+        let mut headers = self.base.clone();
+        let matching = self
+            .domains
+            .iter()
+            .find(|(domain, _)| domain_matches(host, domain));
+        if let Some((_, overrides)) = matching {
+            for (name, value) in overrides {
+                headers.insert(name, value.clone());
+            }
+        }
+        if let Some(value) = accept_language.and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(header::ACCEPT_LANGUAGE, value);
+        }
+        headers
+    }
+}
+
+fn domain_headers(overrides: &DomainIdentity) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    if let Some(user_agent) = &overrides.user_agent {
+        insert(&mut headers, header::USER_AGENT.as_str(), user_agent)?;
+    }
+    if let Some(accept_language) = &overrides.accept_language {
+        insert(
+            &mut headers,
+            header::ACCEPT_LANGUAGE.as_str(),
+            accept_language,
+        )?;
+    }
+    for (name, value) in &overrides.headers {
+        insert(&mut headers, name, value)?;
+    }
+    Ok(headers)
+}
+
+fn insert(headers: &mut HeaderMap, name: &str, value: &str) -> Result<(), String> {
+    let name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| format!("invalid identity header name {name:?}"))?;
+    let value = HeaderValue::from_str(value)
+        .map_err(|_| format!("invalid value for identity header {name}"))?;
+    headers.insert(name, value);
+    Ok(())
+}
+
+/// Check that every header `[identity]` configures is valid.
+pub(crate) fn validate(identity: &Identity) -> Result<(), String> {
+    Resolved::new(identity).map(drop)
+}
+
+/// A reader's `Accept-Language` reduced to what may key a cache entry:
+/// its first few language ranges, lowercased, in order of preference and
+/// without weights. Anything that isn't a language range is dropped, so
+/// clients can't mint endless variants of one render.
+pub fn reader_accept_language(raw: &str) -> Option<String> {
+    let mut ranges: Vec<(f32, String)> = raw
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim().to_ascii_lowercase();
+            let weight = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            let valid = (1..=35).contains(&tag.len())
+                && tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '*');
+            (valid && weight > 0.0).then_some((weight, tag))
+        })
+        .collect();
+    // Stable, so equal weights keep the reader's order.
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut kept: Vec<String> = Vec::new();
+    for (_, tag) in ranges {
+        if !kept.contains(&tag) {
+            kept.push(tag);
+        }
+    }
+    kept.truncate(MAX_READER_LANGUAGES);
+    (!kept.is_empty()).then(|| kept.join(","))
+}
+
+/// Headers to send when fetching `url`. `accept_language`, when given,
+/// is the reader's own and replaces the configured one.
+pub fn headers_for(url: &Url, accept_language: Option<&str>) -> HeaderMap {
+    RESOLVED.headers_for(url.host_str().unwrap_or_default(), accept_language)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn identity() -> Identity {
+        let mut domains = BTreeMap::new();
+        domains.insert(
+            "example.fr".to_owned(),
+            DomainIdentity {
+                accept_language: Some("fr-FR".into()),
+                headers: [("X-Edition".to_owned(), "fr".to_owned())].into(),
+                ..Default::default()
+            },
+        );
+        Identity {
+            user_agent: "reader/1".into(),
+            accept_language: "en-US".into(),
+            headers: [("DNT".to_owned(), "1".to_owned())].into(),
+            forward_accept_language: false,
+            persistent_cookie_domains: Vec::new(),
+            domains,
+        }
+    }
+
+    #[test]
+    fn domain_overrides_apply_to_subdomains_only() {
+        let resolved = Resolved::new(&identity()).unwrap();
+        let news = resolved.headers_for("news.example.fr", None);
+        assert_eq!(news[header::ACCEPT_LANGUAGE], "fr-FR");
+        assert_eq!(news["x-edition"], "fr");
+        assert_eq!(news[header::USER_AGENT], "reader/1");
+        assert_eq!(news["dnt"], "1");
+        let other = resolved.headers_for("notexample.fr", None);
+        assert_eq!(other[header::ACCEPT_LANGUAGE], "en-US");
+        assert!(!other.contains_key("x-edition"));
+    }
+
+    #[test]
+    fn forwarded_accept_language_wins() {
+        let resolved = Resolved::new(&identity()).unwrap();
+        let headers = resolved.headers_for("www.example.fr", Some("de-DE,de;q=0.9"));
+        assert_eq!(headers[header::ACCEPT_LANGUAGE], "de-DE,de;q=0.9");
+    }
+
+    #[test]
+    fn invalid_headers_fail_validation() {
+        let mut bad = identity();
+        bad.headers.insert("Bad Name".into(), "1".into());
+        assert!(validate(&bad).is_err());
+        let mut bad = identity();
+        bad.user_agent = "reader\n/1".into();
+        assert!(validate(&bad).is_err());
+        assert!(validate(&identity()).is_ok());
+    }
+
+    #[test]
+    fn reader_languages_are_bounded_and_normalised() {
+        assert_eq!(
+            reader_accept_language("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.7, *;q=0.5").as_deref(),
+            Some("fr-ch,fr,en")
+        );
+        assert_eq!(
+            reader_accept_language("en;q=0.5, DE").as_deref(),
+            Some("de,en")
+        );
+        assert_eq!(reader_accept_language("x=y, <script>"), None);
+        assert_eq!(reader_accept_language(&"a".repeat(10_000)), None);
+    }
+}
@@ -0,0 +1,36 @@
+//! Outbound HTTP: the [`Fetcher`] renders download through, the reqwest
+//! clients behind its network implementation ([`fetch`]), the guard
+//! every request they make goes through ([`guard`]), the headers they
+//! identify themselves with ([`identity`]), the per-render cookie jars
+//! ([`cookies`]), the encoding sniffing that turns article bytes into
+//! text ([`charset`]), the retries and per-host limits fetches run
+//! under ([`retry`], [`host_limit`]), the opt-in `robots.txt` policy
+//! ([`polite`], [`robots`]), and the recorded responses tests fetch from
+//! instead ([`fixture`]), captured by [`record`].
+
+pub mod charset;
+pub mod cookies;
+mod fetch;
+mod fetcher;
+pub mod fixture;
+pub mod guard;
+mod host_limit;
+pub mod identity;
+pub mod polite;
+pub mod record;
+mod retry;
+pub mod robots;
+
+pub use fetch::{http_get, http_get_bytes, FetchOptions, FetchedPage, Session, Validators};
+pub use fetcher::{FetchFuture, FetchSession, Fetcher, NetworkFetcher};
+pub use fixture::FixtureFetcher;
+pub use polite::PoliteFetcher;
+pub use record::RecordingFetcher;
+
+/// Whether `host` is `domain` or one of its subdomains. A leading dot on
+/// `domain` and a trailing one on `host` are ignored.
+pub(crate) fn domain_matches(host: &str, domain: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
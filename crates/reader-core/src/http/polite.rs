@@ -0,0 +1,314 @@
+//! [`PoliteFetcher`]: the opt-in crawl politeness layer (`[robots]` in
+//! the config). Wraps another [`Fetcher`] so that every article fetch
+//! first checks the host's `robots.txt`, refusing disallowed pages with
+//! [`HttpError::DisallowedByRobots`], then waits until at least the
+//! configured delay has passed since the last hit to that host.
+//!
+//! Each host's `robots.txt` is fetched through the render's own session
+//! and kept for `cache_ttl`. One that can't be reached (network error,
+//! `5xx`) disallows the whole host, as RFC 9309 asks, but only for a few
+//! minutes; any other error status, such as a `404`, means no rules. Image downloads pass straight through: they
+//! are the page's own resources, already rate limited per host by
+//! [`super::host_limit`].
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use reqwest::Url;
+
+use super::{
+    fetch::{FetchOptions, FetchedPage},
+    fetcher::{FetchFuture, FetchSession, Fetcher},
+    robots::Robots,
+};
+use crate::{config::CONFIG, http_error::HttpError};
+
+/// How long an unreachable `robots.txt` keeps its host blocked before
+/// it is tried again.
+const UNREACHABLE_TTL: Duration = Duration::from_secs(300);
+
+/// Longest `Crawl-delay` honoured; a render shouldn't wait minutes.
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(10);
+
+/// A [`Fetcher`] that honours `robots.txt` and per-host delays for the
+/// article fetches made through `inner`. Clones share their cache and
+/// delays.
+#[derive(Clone)]
+pub struct PoliteFetcher {
+    inner: Arc<dyn Fetcher>,
+    policy: Arc<Policy>,
+}
+
+struct Policy {
+    agent: String,
+    min_delay: Duration,
+    ttl: Duration,
+    /// Parsed `robots.txt` per origin, with when it expires.
+    robots: Mutex<HashMap<String, (Instant, Arc<Robots>)>>,
+    /// Earliest moment the next fetch from each host may go out.
+    next_hit: Mutex<HashMap<String, Instant>>,
+}
+
+impl PoliteFetcher {
+    /// Wrap `inner` with the `[robots]` settings of the config.
+    pub fn new(inner: Arc<dyn Fetcher>) -> Self {
+        Self::with_policy(
+            inner,
+            &CONFIG.robots.agent,
+            Duration::from_millis(CONFIG.robots.min_delay_ms),
+            Duration::from_secs(CONFIG.robots.cache_ttl),
+        )
+    }
+
+    fn with_policy(
+        inner: Arc<dyn Fetcher>,
+        agent: &str,
+        min_delay: Duration,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            inner,
+            policy: Arc::new(Policy {
+                agent: agent.to_owned(),
+                min_delay,
+                ttl,
+                robots: Mutex::default(),
+                next_hit: Mutex::default(),
+            }),
+        }
+    }
+}
+
+impl Policy {
+    /// The rules for `url`'s origin, fetching its `robots.txt` through
+    /// `session` unless a fresh copy is cached.
+    async fn robots_for(&self, url: &Url, session: &dyn FetchSession) -> Arc<Robots> {
+        let origin = url.origin().ascii_serialization();
+        let cached = self.robots.lock().ok().and_then(|robots| {
+            robots
+                .get(&origin)
+                .filter(|(expires, _)| *expires > Instant::now())
+                .map(|(_, robots)| Arc::clone(robots))
+        });
+        if let Some(robots) = cached {
+            return robots;
+        }
+        self.wait_turn(url, None).await;
+        let robots_url = format!("{}/robots.txt", origin);
+        let (robots, ttl) = match session
+            .get_page(&robots_url, &FetchOptions::default())
+            .await
+        {
+            Ok(Some(page)) if page.status.is_success() => {
+                (Robots::parse(&page.body, &self.agent), self.ttl)
+            }
+            Ok(Some(page)) if page.status.is_server_error() => {
+                (Robots::disallow_all(), self.ttl.min(UNREACHABLE_TTL))
+            }
+            Err(
+                HttpError::Request(_)
+                | HttpError::Unavailable { .. }
+                | HttpError::ConnectTimeout { .. }
+                | HttpError::ReadTimeout { .. }
+                | HttpError::TotalTimeout { .. },
+            ) => (Robots::disallow_all(), self.ttl.min(UNREACHABLE_TTL)),
+            // Nothing usable, but the host answered: no restrictions.
+            Ok(_) | Err(_) => (Robots::allow_all(), self.ttl),
+        };
+        let robots = Arc::new(robots);
+        if let Ok(mut cache) = self.robots.lock() {
+            let now = Instant::now();
+            cache.retain(|_, (expires, _)| *expires > now);
+            cache.insert(origin, (now + ttl, Arc::clone(&robots)));
+        }
+        robots
+    }
+
+    /// Wait until a fetch from `url`'s host may go out, and book the
+    /// next slot after it. Concurrent callers queue up one delay apart.
+    async fn wait_turn(&self, url: &Url, crawl_delay: Option<Duration>) {
+        let delay = crawl_delay
+            .map_or(self.min_delay, |asked| asked.min(MAX_CRAWL_DELAY))
+            .max(self.min_delay);
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let slot = {
+            let Ok(mut next_hit) = self.next_hit.lock() else {
+                return;
+            };
+            let now = Instant::now();
+            next_hit.retain(|_, next| *next > now);
+            let slot = next_hit.get(&host).copied().unwrap_or(now).max(now);
+            next_hit.insert(host, slot + delay);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+impl Fetcher for PoliteFetcher {
+    fn session(&self) -> Result<Box<dyn FetchSession>, HttpError> {
+        Ok(Box::new(PoliteSession {
+            inner: self.inner.session()?,
+            policy: Arc::clone(&self.policy),
+        }))
+    }
+
+    fn get_bytes(&self, url: &str) -> Result<Vec<u8>, HttpError> {
+        self.inner.get_bytes(url)
+    }
+
+    fn image_cache_dir(&self) -> PathBuf {
+        self.inner.image_cache_dir()
+    }
+}
+
+struct PoliteSession {
+    inner: Box<dyn FetchSession>,
+    policy: Arc<Policy>,
+}
+
+impl FetchSession for PoliteSession {
+    fn get_page<'a>(
+        &'a self,
+        url: &'a str,
+        options: &'a FetchOptions,
+    ) -> FetchFuture<'a, Option<FetchedPage>> {
+        Box::pin(async move {
+            let parsed = Url::parse(url).map_err(|_| HttpError::InvalidUrl(url.to_owned()))?;
+            let robots = self.policy.robots_for(&parsed, self.inner.as_ref()).await;
+            let path = match parsed.query() {
+                Some(query) => format!("{}?{}", parsed.path(), query),
+                None => parsed.path().to_owned(),
+            };
+            if !robots.allows(&path) {
+                return Err(HttpError::DisallowedByRobots(url.to_owned()));
+            }
+            self.policy.wait_turn(&parsed, robots.crawl_delay()).await;
+            self.inner.get_page(url, options).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::http::fixture::{Exchange, FixtureFetcher};
+
+    fn page(body: &str) -> Exchange {
+        Exchange {
+            body: body.as_bytes().to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn refuses_disallowed_pages_and_spaces_hits() {
+        let origin = FixtureFetcher::new(HashMap::from([
+            (
+                "https://example.com/robots.txt".to_owned(),
+                page("User-agent: clean_reader\nDisallow: /private\n"),
+            ),
+            ("https://example.com/post".to_owned(), page("<p>post</p>")),
+            ("https://example.com/private/x".to_owned(), page("<p>x</p>")),
+        ]));
+        let delay = Duration::from_millis(50);
+        let fetcher = PoliteFetcher::with_policy(
+            Arc::new(origin),
+            "clean_reader",
+            delay,
+            Duration::from_secs(60),
+        );
+        let session = fetcher.session().unwrap();
+        let options = FetchOptions::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let start = Instant::now();
+            let post = session.get_page("https://example.com/post", &options).await;
+            assert_eq!(post.unwrap().unwrap().body, "<p>post</p>");
+            // One hit for robots.txt, then the post a delay later.
+            assert!(start.elapsed() >= delay);
+            assert!(matches!(
+                session.get_page("https://example.com/private/x", &options).await,
+                Err(HttpError::DisallowedByRobots(url)) if url.ends_with("/private/x")
+            ));
+        });
+    }
+
+    #[test]
+    fn missing_robots_allows_everything() {
+        let origin = FixtureFetcher::new(HashMap::from([(
+            "https://example.org/post".to_owned(),
+            page("<p>post</p>"),
+        )]));
+        let fetcher = PoliteFetcher::with_policy(
+            Arc::new(origin),
+            "clean_reader",
+            Duration::ZERO,
+            Duration::from_secs(60),
+        );
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let post = runtime.block_on(async {
+            let session = fetcher.session().unwrap();
+            session
+                .get_page("https://example.org/post", &FetchOptions::default())
+                .await
+        });
+        assert!(post.unwrap().is_some());
+    }
+
+    #[test]
+    fn robots_error_status_is_not_parsed_as_rules() {
+        let error = |status| Exchange {
+            status,
+            ..page("<html><body>User-agent: *</body></html>")
+        };
+        let origin = FixtureFetcher::new(HashMap::from([
+            (
+                "https://down.example/robots.txt".to_owned(),
+                error(StatusCode::INTERNAL_SERVER_ERROR),
+            ),
+            ("https://down.example/post".to_owned(), page("<p>post</p>")),
+            (
+                "https://gone.example/robots.txt".to_owned(),
+                error(StatusCode::NOT_FOUND),
+            ),
+            ("https://gone.example/post".to_owned(), page("<p>post</p>")),
+        ]));
+        let fetcher = PoliteFetcher::with_policy(
+            Arc::new(origin),
+            "clean_reader",
+            Duration::ZERO,
+            Duration::from_secs(60),
+        );
+        let session = fetcher.session().unwrap();
+        let options = FetchOptions::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert!(matches!(
+                session
+                    .get_page("https://down.example/post", &options)
+                    .await,
+                Err(HttpError::DisallowedByRobots(_))
+            ));
+            let post = session
+                .get_page("https://gone.example/post", &options)
+                .await;
+            assert!(post.unwrap().is_some());
+        });
+    }
+}
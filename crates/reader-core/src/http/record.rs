@@ -0,0 +1,198 @@
+//! [`RecordingFetcher`]: wraps another [`Fetcher`] and keeps a copy of
+//! every successful exchange made through it — article, AMP page,
+//! images — so a render can be frozen into a fixture bundle that
+//! [`FixtureFetcher`](super::fixture::FixtureFetcher) replays offline.
+//!
+//! Pages are recorded as the decoded text the pipeline saw, saved as
+//! UTF-8 with a matching `Content-Type`, so replay decodes them to the
+//! same string whatever their original encoding.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use reqwest::StatusCode;
+
+use super::{
+    fetch::{FetchOptions, FetchedPage},
+    fetcher::{FetchFuture, FetchSession, Fetcher},
+    fixture::{ExchangeEntry, Manifest, MANIFEST},
+};
+use crate::{hash::sha256, http_error::HttpError};
+
+/// Recorded exchanges with their bodies, in the order they completed.
+type Recorded = Arc<Mutex<Vec<(ExchangeEntry, Vec<u8>)>>>;
+
+/// A [`Fetcher`] that records what it fetches through `inner`. Clones
+/// share one recording.
+#[derive(Clone)]
+pub struct RecordingFetcher {
+    inner: Arc<dyn Fetcher>,
+    recorded: Recorded,
+}
+
+impl RecordingFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>) -> Self {
+        Self {
+            inner,
+            recorded: Arc::default(),
+        }
+    }
+
+    /// Write everything recorded so far to `dir` as a fixture bundle
+    /// whose article is `article`. Returns the number of exchanges saved.
+    pub fn save(&self, dir: &Path, article: &str) -> Result<usize, HttpError> {
+        let bundle_error = |e: &dyn std::fmt::Display| HttpError::Fixture {
+            path: dir.display().to_string(),
+            reason: e.to_string(),
+        };
+        let recorded = self.recorded.lock().map_err(|e| bundle_error(&e))?;
+        std::fs::create_dir_all(dir).map_err(|e| bundle_error(&e))?;
+        let mut manifest = Manifest {
+            article: Some(article.to_owned()),
+            recorded: Some(httpdate::fmt_http_date(SystemTime::now())),
+            license: None,
+            exchanges: Vec::with_capacity(recorded.len()),
+        };
+        for (entry, body) in recorded.iter() {
+            std::fs::write(dir.join(&entry.file), body).map_err(|e| bundle_error(&e))?;
+            manifest.exchanges.push(entry.clone());
+        }
+        let manifest = toml::to_string(&manifest).map_err(|e| bundle_error(&e))?;
+        std::fs::write(dir.join(MANIFEST), manifest).map_err(|e| bundle_error(&e))?;
+        Ok(recorded.len())
+    }
+}
+
+/// Add an exchange to the recording, replacing an earlier one for the
+/// same URL. Body files are named after a hash of the URL.
+fn record(recorded: &Recorded, mut entry: ExchangeEntry, extension: &str, body: Vec<u8>) {
+    let Ok(mut recorded) = recorded.lock() else {
+        return;
+    };
+    recorded.retain(|(existing, _)| existing.url != entry.url);
+    entry.file = format!("{}.{}", &sha256(&entry.url)[..16], extension);
+    recorded.push((entry, body));
+}
+
+impl Fetcher for RecordingFetcher {
+    fn session(&self) -> Result<Box<dyn FetchSession>, HttpError> {
+        Ok(Box::new(RecordingSession {
+            inner: self.inner.session()?,
+            recorded: Arc::clone(&self.recorded),
+        }))
+    }
+
+    fn get_bytes(&self, url: &str) -> Result<Vec<u8>, HttpError> {
+        let bytes = self.inner.get_bytes(url)?;
+        let entry = ExchangeEntry {
+            url: url.to_owned(),
+            ..Default::default()
+        };
+        record(&self.recorded, entry, "bin", bytes.clone());
+        Ok(bytes)
+    }
+
+    fn image_cache_dir(&self) -> PathBuf {
+        self.inner.image_cache_dir()
+    }
+}
+
+struct RecordingSession {
+    inner: Box<dyn FetchSession>,
+    recorded: Recorded,
+}
+
+impl FetchSession for RecordingSession {
+    fn get_page<'a>(
+        &'a self,
+        url: &'a str,
+        options: &'a FetchOptions,
+    ) -> FetchFuture<'a, Option<FetchedPage>> {
+        Box::pin(async move {
+            let page = self.inner.get_page(url, options).await?;
+            if let Some(page) = &page {
+                let entry = ExchangeEntry {
+                    url: url.to_owned(),
+                    final_url: (page.url.as_str() != url).then(|| page.url.to_string()),
+                    status: (page.status != StatusCode::OK).then(|| page.status.as_u16()),
+                    content_type: Some("text/html; charset=utf-8".into()),
+                    etag: page.validators.etag.clone(),
+                    last_modified: page.validators.last_modified.clone(),
+                    file: String::new(),
+                };
+                record(
+                    &self.recorded,
+                    entry,
+                    "html",
+                    page.body.clone().into_bytes(),
+                );
+            }
+            Ok(page)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::http::fixture::{Exchange, FixtureFetcher, ScratchDir};
+
+    #[test]
+    fn recorded_bundle_replays_the_same_responses() {
+        let page = Exchange {
+            final_url: Some("https://example.com/post".into()),
+            content_type: Some("text/html; charset=windows-1252".into()),
+            body: b"<p>caf\xe9</p>".to_vec(),
+            ..Default::default()
+        };
+        let image = Exchange {
+            body: b"png".to_vec(),
+            ..Default::default()
+        };
+        let origin = FixtureFetcher::new(HashMap::from([
+            ("http://example.com/post".to_owned(), page),
+            ("https://example.com/a.png".to_owned(), image),
+        ]));
+        let recorder = RecordingFetcher::new(Arc::new(origin));
+        let session = recorder.session().unwrap();
+        let options = FetchOptions::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let live = runtime
+            .block_on(session.get_page("http://example.com/post", &options))
+            .unwrap()
+            .unwrap();
+        recorder.get_bytes("https://example.com/a.png").unwrap();
+
+        let scratch = ScratchDir::new("clean_reader_recorded_bundle");
+        assert_eq!(
+            recorder
+                .save(&scratch.0, "http://example.com/post")
+                .unwrap(),
+            2
+        );
+        let replay = FixtureFetcher::from_dir(&scratch.0).unwrap();
+        assert_eq!(replay.article(), Some("http://example.com/post"));
+        let replayed = runtime
+            .block_on(
+                replay
+                    .session()
+                    .unwrap()
+                    .get_page("http://example.com/post", &options),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(replayed.body, live.body);
+        assert_eq!(replayed.url, live.url);
+        assert_eq!(
+            replay.get_bytes("https://example.com/a.png").unwrap(),
+            b"png"
+        );
+    }
+}
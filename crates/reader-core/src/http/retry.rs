@@ -0,0 +1,131 @@
+//! Retries for transient fetch failures. Every fetch is a GET, so any
+//! attempt that failed before a full response arrived — refused or reset
+//! connection, connect or read timeout, `429` / `502` / `503` / `504` —
+//! can be repeated safely. Attempts are spaced by exponential backoff
+//! with jitter, or by the origin's `Retry-After` when it sends one.
+
+use std::{
+    future::Future,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{header, StatusCode};
+
+use crate::{config::CONFIG, http_error::HttpError};
+
+/// Run `attempt` until it succeeds, fails for good, or
+/// `CONFIG.fetch_retries` retries have been spent.
+pub(super) async fn retrying<T, F, Fut>(mut attempt: F) -> Result<T, HttpError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, HttpError>>,
+{
+    let base = Duration::from_millis(CONFIG.fetch_retry_base_delay_ms);
+    let max = Duration::from_secs(CONFIG.fetch_retry_max_delay);
+    let mut retries = 0;
+    loop {
+        let error = match attempt().await {
+            Err(e) if retries < CONFIG.fetch_retries && is_transient(&e) => e,
+            result => return result,
+        };
+        let delay = match error {
+            HttpError::Unavailable {
+                retry_after: Some(wait),
+                ..
+            } if wait > max => return Err(error),
+            HttpError::Unavailable {
+                retry_after: Some(wait),
+                ..
+            } => wait,
+            _ => backoff(retries, base, max, rand::random()),
+        };
+        retries += 1;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Whether an attempt that failed with `e` may succeed if repeated.
+fn is_transient(e: &HttpError) -> bool {
+    match e {
+        HttpError::Request(e) => e.is_connect() || e.is_request() || e.is_body(),
+        HttpError::ConnectTimeout { .. }
+        | HttpError::ReadTimeout { .. }
+        | HttpError::Unavailable { .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether a response with `status` is worth retrying.
+pub(super) fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// The error for a retryable response, carrying its `Retry-After`.
+pub(super) fn unavailable(resp: &reqwest::Response) -> HttpError {
+    HttpError::Unavailable {
+        status: resp.status().as_u16(),
+        retry_after: resp
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now())),
+    }
+}
+
+/// Delay before retry number `retry` (from 0): `base` doubled per retry,
+/// capped at `max`, then spread over its upper half by `jitter` in
+/// `[0, 1)` so clients failing together don't retry together.
+fn backoff(retry: u32, base: Duration, max: Duration, jitter: f64) -> Duration {
+    let delay = base.saturating_mul(2u32.saturating_pow(retry)).min(max);
+    delay / 2 + (delay / 2).mul_f64(jitter)
+}
+
+/// A `Retry-After` value, either delta-seconds or an HTTP date.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter() {
+        let base = Duration::from_millis(500);
+        let max = Duration::from_secs(4);
+        assert_eq!(backoff(0, base, max, 0.0), Duration::from_millis(250));
+        assert_eq!(backoff(0, base, max, 0.5), Duration::from_millis(375));
+        assert_eq!(backoff(2, base, max, 0.0), Duration::from_secs(1));
+        assert_eq!(backoff(10, base, max, 0.0), Duration::from_secs(2));
+        assert!(backoff(40, base, max, 0.999) < max);
+    }
+
+    #[test]
+    fn retry_after_seconds_and_dates() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! `robots.txt` parsing and matching, after RFC 9309: the rules of every
+//! group naming our agent token (or of the `*` groups when none does),
+//! longest matching pattern wins, `Allow` wins ties, `*` matches any run
+//! of characters and a trailing `$` anchors the end of the path.
+
+use std::time::Duration;
+
+/// The rules a `robots.txt` sets for one agent.
+#[derive(Clone, Debug, Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Robots {
+    /// Rules that allow everything, for a host without a `robots.txt`.
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Rules that allow nothing, for a host whose `robots.txt` can't be
+    /// reached.
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".into(),
+            }],
+            crawl_delay: None,
+        }
+    }
+
+    /// The rules `txt` sets for `agent`.
+    pub fn parse(txt: &str, agent: &str) -> Self {
+        let mut specific: Option<Robots> = None;
+        let mut wildcard: Option<Robots> = None;
+        // Which of the two the group being read applies to.
+        let (mut for_agent, mut for_wildcard) = (false, false);
+        let mut in_rules = false;
+        for line in txt.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        (for_agent, for_wildcard, in_rules) = (false, false, false);
+                    }
+                    let token = value.split('/').next().unwrap_or_default().trim();
+                    if token == "*" {
+                        for_wildcard = true;
+                        wildcard.get_or_insert_with(Robots::default);
+                    } else if token.eq_ignore_ascii_case(agent) {
+                        for_agent = true;
+                        specific.get_or_insert_with(Robots::default);
+                    }
+                }
+                key @ ("allow" | "disallow" | "crawl-delay") => {
+                    in_rules = true;
+                    let groups = [
+                        specific.as_mut().filter(|_| for_agent),
+                        wildcard.as_mut().filter(|_| for_wildcard),
+                    ];
+                    for robots in groups.into_iter().flatten() {
+                        robots.add(key, value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        specific.or(wildcard).unwrap_or_default()
+    }
+
+    fn add(&mut self, key: &str, value: &str) {
+        match key {
+            "crawl-delay" => {
+                if let Ok(secs) = value.parse::<f64>() {
+                    self.crawl_delay = Duration::try_from_secs_f64(secs).ok();
+                }
+            }
+            // An empty `Disallow:` disallows nothing.
+            _ if value.is_empty() => {}
+            _ => self.rules.push(Rule {
+                allow: key == "allow",
+                pattern: value.to_owned(),
+            }),
+        }
+    }
+
+    /// Whether `path` (path and query of a URL) may be fetched.
+    pub fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+
+    /// The `Crawl-delay` asked for, if any.
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+}
+
+/// Whether `path` matches the rule `pattern`.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i + 1 == parts.len() {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXT: &str = "\
+User-agent: *
+Disallow: /private/
+Allow: /private/press
+Crawl-delay: 5
+
+# Us, and only us.
+User-agent: Clean_Reader/1.0
+User-agent: other
+Disallow: /*.pdf$
+Disallow: /drafts
+Allow: /drafts/published
+";
+
+    #[test]
+    fn picks_the_agent_group_over_the_wildcard() {
+        let ours = Robots::parse(TXT, "clean_reader");
+        assert!(ours.allows("/private/thing"));
+        assert!(!ours.allows("/drafts/1"));
+        assert!(ours.allows("/drafts/published/1"));
+        assert!(!ours.allows("/docs/a.pdf"));
+        assert!(ours.allows("/docs/a.pdf?download=1"));
+        assert_eq!(ours.crawl_delay(), None);
+
+        let anyone = Robots::parse(TXT, "someone");
+        assert!(!anyone.allows("/private/thing"));
+        assert!(anyone.allows("/private/press/release"));
+        assert!(anyone.allows("/drafts/1"));
+        assert_eq!(anyone.crawl_delay(), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn wildcards_and_anchors() {
+        assert!(matches("/a*b", "/a/x/b/c"));
+        assert!(!matches("/a*b", "/a/x/c"));
+        assert!(matches("/a$", "/a"));
+        assert!(!matches("/a$", "/ab"));
+        assert!(matches("/*.php$", "/x/index.php"));
+        assert!(!matches("/*.php$", "/x/index.php5"));
+        assert!(matches("/", "/anything"));
+    }
+
+    #[test]
+    fn empty_or_missing_rules_allow_everything() {
+        assert!(Robots::parse("User-agent: *\nDisallow:\n", "clean_reader").allows("/"));
+        assert!(Robots::parse("", "clean_reader").allows("/"));
+        assert!(!Robots::disallow_all().allows("/"));
+    }
+}
@@ -1,9 +1,75 @@
+use std::{error::Error as _, net::IpAddr, time::Duration};
+
+use crate::config::CONFIG;
+
 /// Errors that can come out of the HTTP fetch path.
 #[derive(Debug, thiserror::Error)]
 pub enum HttpError {
     #[error("HTTP request failed: {0}")]
-    Request(#[from] reqwest::Error),
+    Request(reqwest::Error),
 
     #[error("Response body exceeded {limit} bytes")]
     TooLarge { limit: u64 },
+
+    #[error("Connecting timed out after {secs}s")]
+    ConnectTimeout { secs: u64 },
+
+    #[error("No data received for {secs}s")]
+    ReadTimeout { secs: u64 },
+
+    #[error("Fetch did not complete within {secs}s")]
+    TotalTimeout { secs: u64 },
+
+    #[error("Origin unavailable (HTTP {status})")]
+    Unavailable {
+        status: u16,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("Blocked by robots.txt: {0}")]
+    DisallowedByRobots(String),
+
+    #[error("Not a valid URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Refusing to fetch {host}: {ip} is a blocked address")]
+    BlockedAddress { host: String, ip: IpAddr },
+
+    #[error("No recorded response for {0}")]
+    NotRecorded(String),
+
+    #[error("Bad fixture directory {path}: {reason}")]
+    Fixture { path: String, reason: String },
+}
+
+impl From<reqwest::Error> for HttpError {
+    /// The fetch guard runs inside reqwest (resolver, redirect policy),
+    /// so its rejection arrives wrapped in a `reqwest::Error`. Unwrap it
+    /// so callers see [`HttpError::BlockedAddress`] rather than an opaque
+    /// request failure. Timeouts enforced by the client map to their own
+    /// variants the same way.
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return if e.is_connect() {
+                HttpError::ConnectTimeout {
+                    secs: CONFIG.fetch_connect_timeout,
+                }
+            } else {
+                HttpError::TotalTimeout {
+                    secs: CONFIG.fetch_total_timeout,
+                }
+            };
+        }
+        let mut source = e.source();
+        while let Some(inner) = source {
+            if let Some(HttpError::BlockedAddress { host, ip }) = inner.downcast_ref() {
+                return HttpError::BlockedAddress {
+                    host: host.clone(),
+                    ip: *ip,
+                };
+            }
+            source = inner.source();
+        }
+        HttpError::Request(e)
+    }
 }
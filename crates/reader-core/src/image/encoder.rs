@@ -6,7 +6,7 @@
 //! reader-core can trigger re-encodes without depending on the actor
 //! layer directly.
 
-use std::{io::Cursor, path::Path, path::PathBuf};
+use std::{io::Cursor, path::PathBuf, sync::Arc};
 
 use image::io::Reader;
 use imgref::ImgVec;
@@ -14,7 +14,7 @@ use once_cell::sync::OnceCell;
 use ravif::Encoder;
 use rgb::RGBA;
 
-use crate::{config::CONFIG, hash::sha256};
+use crate::{cache, config::CONFIG, hash::sha256, http::Fetcher};
 
 use super::{ImageError, ImageTicket, ResolvedImage};
 
@@ -31,10 +31,12 @@ const IMAGE_HASH_PREFIX_LEN: usize = 8;
 
 /// Function signature of a registered image-encoder backend.
 ///
-/// Called with the source URL and the cache-file path we want the `.avif`
-/// written to. Returns a ticket the caller can wait on, or `None` if the
-/// backend declines (no worker available, bad URL, …).
-pub type EncoderFn = Box<dyn Fn(String, PathBuf) -> Option<ImageTicket> + Send + Sync + 'static>;
+/// Called with the source URL, the cache-file path we want the `.avif`
+/// written to, and the render's fetcher to download the source through.
+/// Returns a ticket the caller can wait on, or `None` if the backend
+/// declines (no worker available, bad URL, …).
+pub type EncoderFn =
+    Box<dyn Fn(String, PathBuf, Arc<dyn Fetcher>) -> Option<ImageTicket> + Send + Sync + 'static>;
 
 static ENCODER: OnceCell<EncoderFn> = OnceCell::new();
 
@@ -46,37 +48,44 @@ pub fn register_encoder(encoder: EncoderFn) {
 
 /// Resolve an image URL to its final `<img src>` value, launching a
 /// re-encode worker if appropriate.
-pub fn get_image_url(url: &str) -> ResolvedImage {
+pub fn get_image_url(url: &str, fetcher: &Arc<dyn Fetcher>) -> ResolvedImage {
     if !CONFIG.recompress_images {
-        return ResolvedImage {
-            url: url.to_owned(),
-            ticket: None,
-        };
+        return ResolvedImage::original(url);
     }
     let hash = sha256(url);
     let short_hash = &hash[..IMAGE_HASH_PREFIX_LEN];
-    let cache_path = format!("{}/images/{}.avif", CONFIG.cache_folder, short_hash);
-    if Path::new(&cache_path).exists() {
+    // Renders keep pointing at `/i/` after the re-encode is evicted;
+    // from then on it redirects to the original.
+    let record_source = || {
+        if let Err(e) = cache::record_image_source(short_hash, url) {
+            eprintln!("image source {}: {}", url, e);
+        }
+    };
+    let cache_path = fetcher
+        .image_cache_dir()
+        .join(format!("{}.avif", short_hash));
+    if cache_path.exists() {
+        record_source();
         return ResolvedImage {
             url: format!("/i/{}", short_hash),
             ticket: None,
+            cache_path: Some(cache_path),
         };
     }
     let reencodable = REENCODABLE_EXTENSIONS.iter().any(|ext| url.contains(ext));
     let ticket = reencodable
         .then(|| ENCODER.get())
         .flatten()
-        .and_then(|encoder| encoder(url.to_owned(), PathBuf::from(&cache_path)));
+        .and_then(|encoder| encoder(url.to_owned(), cache_path.clone(), Arc::clone(fetcher)));
     if ticket.is_some() {
+        record_source();
         return ResolvedImage {
             url: format!("/i/{}", short_hash),
             ticket,
+            cache_path: Some(cache_path),
         };
     }
-    ResolvedImage {
-        url: url.to_owned(),
-        ticket: None,
-    }
+    ResolvedImage::original(url)
 }
 
 pub fn encode_avif(image: &[u8]) -> Result<Vec<u8>> {
@@ -0,0 +1,200 @@
+//! `data:` URI embedding for self-contained (Download and EPUB) renders.
+//!
+//! The HTML compiler emits self-contained `<img>` tags exactly like View
+//! ones and records each of them, in every mode, as a [`PendingImage`]. Once every
+//! re-encode ticket has landed, [`embed_images`] swaps the `src` values
+//! for inline data — the `.avif` when we have one, the original bytes
+//! otherwise — until the per-article budget runs out.
+
+use std::{collections::HashSet, path::PathBuf};
+
+use crate::http::Fetcher;
+
+/// An `<img>` written into a self-contained body, waiting to be inlined.
+#[derive(Clone, Debug)]
+pub struct PendingImage {
+    /// The exact `src` value emitted by the compiler.
+    pub src: String,
+    /// Remote URL of the image, fetched when no re-encoded copy exists
+    /// and linked from the placeholder when the image can't be embedded.
+    pub original: String,
+    /// Where the re-encoded `.avif` lives, if one was requested.
+    pub cache_path: Option<PathBuf>,
+}
+
+/// Replace every pending `<img>` in `body` with a `data:` URI, spending
+/// at most `budget` bytes of encoded URI across the whole article.
+/// Images that don't fit, or whose bytes can't be loaded, become a
+/// captioned placeholder linking to the original.
+///
+/// `epub` embeds only originals in one of EPUB's core image types
+/// (JPEG, PNG, GIF, WebP, SVG), never the `.avif`: readers promise
+/// nothing else. Originals are downloaded through `fetcher`.
+pub fn embed_images(
+    mut body: String,
+    pending: &[PendingImage],
+    budget: u64,
+    epub: bool,
+    fetcher: &dyn Fetcher,
+) -> String {
+    let mut spent = 0;
+    let mut seen = HashSet::new();
+    for image in pending {
+        if !seen.insert(image.src.as_str()) {
+            continue;
+        }
+        let occurrences = body.matches(&src_attr(&image.src)).count() as u64;
+        if occurrences == 0 {
+            continue;
+        }
+        body = match data_uri(image, epub, fetcher) {
+            Some(uri) if spent + uri.len() as u64 * occurrences <= budget => {
+                spent += uri.len() as u64 * occurrences;
+                body.replace(&src_attr(&image.src), &src_attr(&uri))
+            }
+            Some(_) => replace_img_tags(&body, &image.src, &placeholder(image, "too large")),
+            None => replace_img_tags(&body, &image.src, &placeholder(image, "unavailable")),
+        };
+    }
+    body
+}
+
+fn src_attr(src: &str) -> String {
+    format!(
+        "<img src=\"{}\"",
+        html_escape::encode_double_quoted_attribute(src)
+    )
+}
+
+/// Media types every EPUB reader must display.
+const EPUB_CORE_IMAGE_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/svg+xml",
+];
+
+/// Load the bytes for `image` and wrap them as a base64 `data:` URI.
+/// Outside EPUB the re-encoded `.avif` is tried first, so a skipped or
+/// failed re-encode still falls back to the source.
+fn data_uri(image: &PendingImage, epub: bool, fetcher: &dyn Fetcher) -> Option<String> {
+    let (mime, bytes) = if epub {
+        fetch_original(image, fetcher).filter(|(mime, _)| EPUB_CORE_IMAGE_TYPES.contains(mime))
+    } else {
+        read_cached(image).or_else(|| fetch_original(image, fetcher))
+    }?;
+    Some(format!("data:{};base64,{}", mime, base64::encode(bytes)))
+}
+
+fn read_cached(image: &PendingImage) -> Option<(&'static str, Vec<u8>)> {
+    let bytes = std::fs::read(image.cache_path.as_ref()?).ok()?;
+    Some(("image/avif", bytes))
+}
+
+fn fetch_original(image: &PendingImage, fetcher: &dyn Fetcher) -> Option<(&'static str, Vec<u8>)> {
+    let bytes = fetcher
+        .get_bytes(&image.original)
+        .map_err(|e| eprintln!("inline image {}: {}", image.original, e))
+        .ok()?;
+    Some((sniff_mime(&bytes)?, bytes))
+}
+
+/// Guess a MIME type from the leading bytes. SVG isn't a raster format
+/// `image` can identify, so it gets a textual check of its own.
+fn sniff_mime(bytes: &[u8]) -> Option<&'static str> {
+    if let Ok(format) = image::guess_format(bytes) {
+        return Some(format.to_mime_type());
+    }
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]);
+    head.contains("<svg").then_some("image/svg+xml")
+}
+
+/// Swap each whole `<img src="{src}" …>` element for `replacement`.
+fn replace_img_tags(body: &str, src: &str, replacement: &str) -> String {
+    let needle = src_attr(src);
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find(&needle) {
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(replacement);
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn placeholder(image: &PendingImage, reason: &str) -> String {
+    let href = html_escape::encode_double_quoted_attribute(&image.original);
+    format!(
+        "<figure class=\"image-placeholder\"><figcaption>Image not embedded ({}): <a href=\"{}\">view original</a></figcaption></figure>",
+        reason, href
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::fixture::ScratchDir;
+    use crate::http::FixtureFetcher;
+
+    fn cached_image(dir: &ScratchDir, bytes: &[u8]) -> PendingImage {
+        let path = dir.0.join("abcd1234.avif");
+        std::fs::write(&path, bytes).expect("write cached image");
+        PendingImage {
+            src: "/i/abcd1234".into(),
+            original: "https://example.com/a.jpg".into(),
+            cache_path: Some(path),
+        }
+    }
+
+    #[test]
+    fn inlines_cached_avif_within_budget() {
+        let dir = ScratchDir::new("clean_reader_inline");
+        let image = cached_image(&dir, b"avif");
+        let body = embed_images(
+            "<p>x</p><img src=\"/i/abcd1234\">".into(),
+            &[image],
+            1_000,
+            false,
+            &FixtureFetcher::default(),
+        );
+        assert_eq!(
+            body,
+            "<p>x</p><img src=\"data:image/avif;base64,YXZpZg==\">"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_placeholder_over_budget() {
+        let dir = ScratchDir::new("clean_reader_inline");
+        let image = cached_image(&dir, b"avif");
+        let body = embed_images(
+            "<img src=\"/i/abcd1234\"> tail".into(),
+            &[image],
+            4,
+            false,
+            &FixtureFetcher::default(),
+        );
+        assert!(body.starts_with("<figure class=\"image-placeholder\">"));
+        assert!(body.contains("href=\"https://example.com/a.jpg\""));
+        assert!(body.ends_with(" tail"));
+    }
+
+    #[test]
+    fn epub_never_embeds_the_avif() {
+        let dir = ScratchDir::new("clean_reader_inline");
+        let image = cached_image(&dir, b"avif");
+        let body = embed_images(
+            "<img src=\"/i/abcd1234\">".into(),
+            &[image],
+            1_000,
+            true,
+            &FixtureFetcher::default(),
+        );
+        assert!(body.starts_with("<figure class=\"image-placeholder\">"));
+    }
+}
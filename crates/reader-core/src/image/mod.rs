@@ -6,13 +6,18 @@
 //! the dep graph acyclic, the image-actor registers a closure here at
 //! boot time via [`register_encoder`]; `get_image_url` calls through the
 //! registered closure when the template renderer asks for an image.
+//!
+//! Download renders additionally inline their images as `data:` URIs
+//! (see [`embed_images`]) so the saved file works offline.
 
 mod encoder;
 mod error;
+mod inline;
 mod resolved;
 mod ticket;
 
 pub use encoder::{encode_avif, get_image_url, register_encoder, EncoderFn};
 pub use error::ImageError;
+pub use inline::{embed_images, PendingImage};
 pub use resolved::ResolvedImage;
 pub use ticket::ImageTicket;
@@ -1,10 +1,25 @@
+use std::path::PathBuf;
+
 use super::ImageTicket;
 
 /// The outcome of [`super::get_image_url`]: the URL to actually emit in
 /// the final `<img src>` (either the re-encoded `/i/{hash}` path or the
 /// original remote URL), plus an optional [`ImageTicket`] the template
-/// renderer must wait on if re-encoding is in flight.
+/// renderer must wait on if re-encoding is in flight. `cache_path` is
+/// where the `.avif` lives (or will land) when `url` is a `/i/` path.
 pub struct ResolvedImage {
     pub url: String,
     pub ticket: Option<ImageTicket>,
+    pub cache_path: Option<PathBuf>,
+}
+
+impl ResolvedImage {
+    /// Emit the remote URL unchanged, with nothing to wait on.
+    pub fn original(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            ticket: None,
+            cache_path: None,
+        }
+    }
 }
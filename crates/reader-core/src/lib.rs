@@ -1,5 +1,6 @@
 //! Core reader pipeline: HTML parsing, Readability extraction, image
-//! re-encoding scaffolding, template rendering and the on-disk URL cache.
+//! re-encoding scaffolding, template rendering, EPUB packaging and the
+//! on-disk URL cache.
 //!
 //! This crate is the pure "what the server does" layer. It knows nothing
 //! about the actor runtime — the services that wrap it (page-actor,
@@ -7,15 +8,17 @@
 //!
 //! Error types are **per-module**: each feature area exposes its own
 //! narrow error enum (`HttpError`, `NodeError`, `CacheError`,
-//! `ImageError`, `PipelineError`), and the crate-root [`Error`] unions
+//! `ImageError`, `EpubError`, `PipelineError`), and the crate-root [`Error`] unions
 //! them via `#[from]` for callers that want a single aggregate type.
 
 pub mod cache;
 pub mod cache_error;
 pub mod config;
 pub mod context;
+pub mod epub;
 pub mod error;
 pub mod hash;
+pub mod highlight;
 pub mod html_node;
 pub mod html_node_error;
 pub mod http;
@@ -31,6 +34,7 @@ pub mod title_extractor;
 pub mod urls;
 
 pub use cache_error::CacheError;
+pub use epub::EpubError;
 pub use error::{Error, Result};
 pub use html_node_error::NodeError;
 pub use http_error::HttpError;
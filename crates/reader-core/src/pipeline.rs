@@ -3,45 +3,212 @@
 //! [`render`] is the public entry point: given a URL it fetches the HTML
 //! (following `amphtml` hints where present), hands the body to Readability
 //! for content selection, then lowers the result through `HTMLNode`,
-//! `TextCompound` and the askama template to produce the final page.
+//! `TextCompound` and the askama template (or the Markdown compiler) to
+//! produce the final page. [`render_html`] runs the same stages over HTML
+//! supplied by the caller instead of fetched, and [`replay`] over a
+//! fixture bundle saved by [`record`].
+//!
+//! Relative links and images resolve against the document's base URL: its
+//! `<base href>` if it has one, else the URL it was finally served from
+//! after redirects.
+//!
+//! Every download — article, AMP page, images — goes through the
+//! [`Fetcher`] the caller passes in, so tests can render recorded pages
+//! offline.
 //!
 //! All CPU-bound work runs inside `spawn_blocking`; only the network
 //! fetches touch the async executor directly.
 
 use std::collections::HashMap;
 use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
 
 use html5ever::tendril::TendrilSink;
 
 use crate::{
-    context::Context, html_node::HTMLNode, http, pipeline_error::PipelineError,
-    render_mode::RenderMode, score_implementation::starts_with_image, template::render_article,
-    text_element::TextCompound, title_extractor,
+    context::Context,
+    highlight,
+    html_node::HTMLNode,
+    http::{FetchOptions, FetchedPage, Fetcher, FixtureFetcher, RecordingFetcher, Validators},
+    http_error::HttpError,
+    pipeline_error::PipelineError,
+    render_mode::RenderMode,
+    score_implementation::starts_with_image,
+    template::{render_article, render_chapter, render_markdown},
+    text_element::TextCompound,
+    title_extractor,
+    urls::{resolve_document_link, same_site},
 };
 
 type Result<T> = std::result::Result<T, PipelineError>;
 
+/// Version of the rendered output. Bump it whenever a change to the
+/// pipeline, the compilers or the templates alters what a render
+/// produces, so on-disk cache entries from older builds are dropped.
+pub const PIPELINE_VERSION: u32 = 11;
+
+/// A fresh render, with the validators of the article response so the
+/// cache can revalidate it later, and the page's `<link rel="canonical">`
+/// so aliases of one article can share a cache entry. Only a canonical
+/// URL on the site the page was served from is kept.
+#[derive(Clone)]
+pub struct RenderedPage {
+    pub body: String,
+    pub validators: Validators,
+    pub canonical_url: Option<String>,
+    /// Original URLs of the images the render shows.
+    pub images: Vec<String>,
+}
+
+/// What the CPU-bound stages make of one document.
+struct Document {
+    body: String,
+    canonical_url: Option<reqwest::Url>,
+    images: Vec<String>,
+}
+
 /// Fetch a URL and render it through the reader pipeline.
-pub async fn render(url: &str, min_id: &str, mode: RenderMode) -> Result<String> {
-    let html = fetch_with_amp_fallback(url).await?;
+///
+/// With `options.revalidate`, the fetch is conditional on those
+/// validators and `Ok(None)` means the origin reported the page
+/// unchanged, so the caller's stored render is still current.
+pub async fn render(
+    fetcher: &Arc<dyn Fetcher>,
+    url: &str,
+    min_id: &str,
+    mode: RenderMode,
+    options: &FetchOptions,
+) -> Result<Option<RenderedPage>> {
+    let Some(page) = fetch_with_amp_fallback(fetcher.as_ref(), url, options).await? else {
+        return Ok(None);
+    };
+    let document = render_document(fetcher, page.url, page.body, min_id, mode).await?;
+    Ok(Some(RenderedPage {
+        body: document.body,
+        validators: page.validators,
+        canonical_url: document.canonical_url.map(String::from),
+        images: document.images,
+    }))
+}
+
+/// Render `url` through `fetcher` and save everything the render
+/// downloaded as a fixture bundle in `bundle`, together with the original
+/// of every image it shows: a View render links cached re-encodes (or,
+/// without recompression, the originals) instead of downloading them, yet
+/// a replay on an empty cache needs their bytes. Returns the number of
+/// exchanges saved.
+pub async fn record(
+    fetcher: Arc<dyn Fetcher>,
+    url: &str,
+    min_id: &str,
+    bundle: &Path,
+) -> Result<usize> {
+    let recorder = RecordingFetcher::new(fetcher);
+    let recording: Arc<dyn Fetcher> = Arc::new(recorder.clone());
+    let page = render(
+        &recording,
+        url,
+        min_id,
+        RenderMode::View,
+        &FetchOptions::default(),
+    )
+    .await?
+    .ok_or(PipelineError::UnexpectedNotModified)?;
+    let mut images = page.images;
+    images.sort();
+    images.dedup();
+    tokio::task::spawn_blocking(move || {
+        for image in &images {
+            if let Err(e) = recording.get_bytes(image) {
+                eprintln!("recording image {}: {}", image, e);
+            }
+        }
+    })
+    .await
+    .map_err(|_| PipelineError::BlockingCanceled)?;
+    Ok(recorder.save(bundle, url)?)
+}
+
+/// Render a fixture bundle saved by [`record`] entirely from the bundle:
+/// the article and everything it downloads. Images are re-encoded into a
+/// cache of their own (see [`Fetcher::image_cache_dir`]).
+pub async fn replay(bundle: &Path, min_id: &str, mode: RenderMode) -> Result<String> {
+    let fixture = FixtureFetcher::from_dir(bundle)?;
+    let url = fixture
+        .article()
+        .map(str::to_owned)
+        .ok_or_else(|| HttpError::Fixture {
+            path: bundle.display().to_string(),
+            reason: "no article URL in the manifest".into(),
+        })?;
+    let fetcher: Arc<dyn Fetcher> = Arc::new(fixture);
+    render(&fetcher, &url, min_id, mode, &FetchOptions::default())
+        .await?
+        .map(|page| page.body)
+        .ok_or(PipelineError::UnexpectedNotModified)
+}
+
+/// Render HTML the caller already has — a DOM snapshot submitted by the
+/// browser extension — without touching the network for the article
+/// itself. `url` is still needed to resolve relative links and images,
+/// which are downloaded through `fetcher`.
+pub async fn render_html(
+    fetcher: &Arc<dyn Fetcher>,
+    url: &str,
+    html: String,
+    min_id: &str,
+    mode: RenderMode,
+) -> Result<String> {
     let parsed_url =
         reqwest::Url::parse(url).map_err(|e| PipelineError::InvalidUrl(e.to_string()))?;
+    let document = render_document(fetcher, parsed_url, html, min_id, mode).await?;
+    Ok(document.body)
+}
+
+/// Run the CPU-bound stages on `spawn_blocking`.
+async fn render_document(
+    fetcher: &Arc<dyn Fetcher>,
+    url: reqwest::Url,
+    html: String,
+    min_id: &str,
+    mode: RenderMode,
+) -> Result<Document> {
     let min_id = min_id.to_string();
-    tokio::task::spawn_blocking(move || render_fetched_html(html, parsed_url, min_id, mode))
-        .await
-        .map_err(|_| PipelineError::BlockingCanceled)?
+    let fetcher = Arc::clone(fetcher);
+    tokio::task::spawn_blocking(move || {
+        render_fetched_html(fetcher, html, url, min_id, mode, |_| {})
+    })
+    .await
+    .map_err(|_| PipelineError::BlockingCanceled)?
 }
 
 /// Download the article HTML, replacing it with the AMP version if one is
 /// linked and reachable. A malformed link or failed AMP fetch falls back
-/// to the original HTML rather than erroring.
-async fn fetch_with_amp_fallback(url: &str) -> Result<String> {
-    let original = http::http_get(url).await?;
-    let Some(amp_url) = extract_amp_url(&original) else {
-        return Ok(original);
+/// to the original HTML rather than erroring. The validators are always
+/// the original URL's, since that is what gets revalidated; the final URL
+/// is the AMP page's when it is used, since its links are relative to it.
+async fn fetch_with_amp_fallback(
+    fetcher: &dyn Fetcher,
+    url: &str,
+    options: &FetchOptions,
+) -> Result<Option<FetchedPage>> {
+    let session = fetcher.session()?;
+    let Some(mut page) = session.get_page(url, options).await? else {
+        return Ok(None);
     };
-    eprintln!("Using AMPHTML: {}", amp_url);
-    Ok(http::http_get(&amp_url).await.unwrap_or(original))
+    if let Some(amp_url) = extract_amp_url(&page.body) {
+        eprintln!("Using AMPHTML: {}", amp_url);
+        let amp_options = FetchOptions {
+            revalidate: None,
+            ..options.clone()
+        };
+        if let Ok(Some(amp)) = session.get_page(&amp_url, &amp_options).await {
+            page.body = amp.body;
+            page.url = amp.url;
+        }
+    }
+    Ok(Some(page))
 }
 
 /// Scan raw HTML for a `rel="amphtml"` link and return its target.
@@ -52,21 +219,64 @@ fn extract_amp_url(html: &str) -> Option<String> {
     after.split('"').nth(1).map(str::to_owned)
 }
 
+/// A render together with the pretty-printed `TextCompound` tree it was
+/// compiled from — what the golden tests under `tests/golden` compare.
+pub struct Snapshot {
+    pub tree: String,
+    pub body: String,
+}
+
+/// Run the CPU-bound stages over `html`, served from `url`, on the
+/// calling thread, keeping the intermediate tree alongside the output.
+pub fn snapshot(
+    fetcher: Arc<dyn Fetcher>,
+    html: String,
+    url: reqwest::Url,
+    mode: RenderMode,
+) -> Result<Snapshot> {
+    let mut tree = String::new();
+    let document = render_fetched_html(fetcher, html, url, String::new(), mode, |article| {
+        tree = format!("{:#?}", article);
+    })?;
+    Ok(Snapshot {
+        tree,
+        body: document.body,
+    })
+}
+
 /// CPU-bound half of the pipeline: Readability → `HTMLNode` →
-/// `TextCompound` → askama template. Runs inside `spawn_blocking`.
+/// `TextCompound` → askama template or Markdown. Runs inside
+/// `spawn_blocking`. `url` is where the document was served from; the
+/// canonical URL it declares, if any and on the same site, is returned
+/// with the render, as are the images it shows.
+/// `inspect` sees the final `TextCompound` tree before it is compiled.
 fn render_fetched_html(
+    fetcher: Arc<dyn Fetcher>,
     html: String,
-    parsed_url: reqwest::Url,
+    url: reqwest::Url,
     min_id: String,
     mode: RenderMode,
-) -> Result<String> {
-    // Lightweight regex scan for og:title / og:image / <title>, avoiding a
-    // full html5ever parse just for metadata.
+    inspect: impl FnOnce(&TextCompound),
+) -> Result<Document> {
+    // Lightweight regex scan for og:title / og:image / <title> / <base> /
+    // canonical link, avoiding a full html5ever parse just for metadata.
     let mut meta = title_extractor::try_extract_data(&html);
+    let base = meta
+        .base
+        .as_deref()
+        .and_then(|href| resolve_document_link(&url, href))
+        .unwrap_or_else(|| url.clone());
+    let canonical_url = meta
+        .canonical
+        .as_deref()
+        .and_then(|href| resolve_document_link(&base, href))
+        .filter(|canonical| same_site(canonical, &url));
 
     // Readability (Firefox reader-view algorithm) picks the article
-    // subtree and returns it as a serialized HTML fragment.
-    let product = readability::extractor::extract(&mut Cursor::new(html), &parsed_url)
+    // subtree and returns it as a serialized HTML fragment. It drops
+    // `class`, so code language hints are copied aside first.
+    let html = highlight::tag_code_languages(&html);
+    let product = readability::extractor::extract(&mut Cursor::new(html.as_bytes()), &base)
         .map_err(|e| PipelineError::Readability(e.to_string()))?;
     if meta.title.is_none() && !product.title.is_empty() {
         meta.title = Some(product.title);
@@ -84,9 +294,12 @@ fn render_fetched_html(
         meta,
         mode,
         min_id,
-        url: parsed_url,
+        url,
+        base,
+        fetcher,
         map: HashMap::new(),
         count: 0,
+        pending_images: Vec::new(),
     };
     let article =
         TextCompound::from_node(&mut ctx, &html_tree).ok_or(PipelineError::EmptyArticle)?;
@@ -109,6 +322,108 @@ fn render_fetched_html(
     if starts_with_image(&html_tree) {
         ctx.meta.image = None;
     }
+    inspect(&article);
+
+    let body = match mode {
+        RenderMode::Markdown => render_markdown(&[article], &mut ctx),
+        RenderMode::Epub => render_chapter(&[article], &mut ctx),
+        RenderMode::View | RenderMode::Download => render_article(&[article], &mut ctx),
+    }?;
+    Ok(Document {
+        body,
+        canonical_url,
+        images: ctx
+            .pending_images
+            .into_iter()
+            .map(|image| image.original)
+            .filter(|original| !original.is_empty())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::fixture::{Exchange, ScratchDir};
+
+    fn canonical_of(page_url: &str, href: &str) -> Option<String> {
+        let html = format!(
+            "<html><head><link rel=\"canonical\" href=\"{}\"></head><body><article>\
+             <p>A paragraph long enough for Readability to keep it as the article body, \
+             with a few commas, some more words, and then a full stop.</p></article></body></html>",
+            href
+        );
+        let canonical = render_fetched_html(
+            Arc::new(FixtureFetcher::default()),
+            html,
+            page_url.parse().unwrap(),
+            String::new(),
+            RenderMode::View,
+            |_| {},
+        )
+        .unwrap()
+        .canonical_url;
+        canonical.map(String::from)
+    }
 
-    render_article(&[article], &mut ctx)
+    #[test]
+    fn only_same_site_canonical_urls_are_kept() {
+        assert_eq!(
+            canonical_of(
+                "https://www.example.com/a?ref=feed",
+                "https://example.com/a"
+            )
+            .as_deref(),
+            Some("https://example.com/a")
+        );
+        assert_eq!(
+            canonical_of(
+                "https://attacker.example/a",
+                "https://victim.example/article"
+            ),
+            None
+        );
+        assert_eq!(
+            canonical_of(
+                "https://example.com.attacker.example/a",
+                "https://example.com/a"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn a_recorded_bundle_replays_on_an_empty_cache() {
+        let article = "<html><head><title>Post</title></head><body><article>\
+             <p>A paragraph long enough for Readability to keep it as the article body, \
+             with a few commas, some more words, and then a full stop.</p>\
+             <p><img src=\"/a.png\" alt=\"A\"></p>\
+             <p>Another paragraph, so the image sits inside the article, with commas, \
+             more words, and a full stop.</p></article></body></html>";
+        let png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let exchange = |body: Vec<u8>| Exchange {
+            body,
+            ..Default::default()
+        };
+        let origin = FixtureFetcher::new(HashMap::from([
+            (
+                "https://example.com/post".to_owned(),
+                exchange(article.as_bytes().to_vec()),
+            ),
+            ("https://example.com/a.png".to_owned(), exchange(png)),
+        ]));
+        let bundle = ScratchDir::new("clean_reader_pipeline_record");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let saved = record(Arc::new(origin), "https://example.com/post", "", &bundle.0)
+                .await
+                .unwrap();
+            assert_eq!(saved, 2);
+            let replayed = replay(&bundle.0, "", RenderMode::Download).await.unwrap();
+            assert!(replayed.contains("src=\"data:image/png;base64,"));
+        });
+    }
 }
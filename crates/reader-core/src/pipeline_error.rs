@@ -28,4 +28,10 @@ pub enum PipelineError {
 
     #[error("Blocking worker panicked")]
     BlockingCanceled,
+
+    #[error("The origin reported the page unchanged, but there was no stored copy to reuse")]
+    UnexpectedNotModified,
+
+    #[error("Render task failed: {0}")]
+    RenderAborted(String),
 }
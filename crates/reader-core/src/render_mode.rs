@@ -3,16 +3,64 @@
 /// `View` means the article is served back by the same Clean Reader
 /// server, so outbound links get rewritten through `/m/` for one-click
 /// cleaning. `Download` means the HTML is a self-contained file the user
-/// is taking off the server, so links keep their original targets and the
-/// "download this article" footer is suppressed.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// is taking off the server, so links keep their original targets, images
+/// are inlined as `data:` URIs and the "download this article" footer is
+/// suppressed. `Markdown` emits a `.md` document from the same
+/// `TextCompound` tree instead of HTML, with original link and image
+/// targets. `Epub` produces an XHTML chapter document, self-contained like
+/// `Download`, that [`crate::epub::package`] bundles into a book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum RenderMode {
     View,
     Download,
+    Markdown,
+    Epub,
 }
 
 impl RenderMode {
     pub fn is_download(self) -> bool {
         matches!(self, Self::Download)
     }
+
+    pub fn is_epub(self) -> bool {
+        matches!(self, Self::Epub)
+    }
+
+    /// Whether the HTML output leaves the server: links keep their
+    /// original targets and images get inlined.
+    pub fn is_self_contained(self) -> bool {
+        matches!(self, Self::Download | Self::Epub)
+    }
+
+    pub fn is_markdown(self) -> bool {
+        matches!(self, Self::Markdown)
+    }
+
+    /// Stable lowercase name, used in cache keys and file names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::View => "view",
+            Self::Download => "download",
+            Self::Markdown => "markdown",
+            Self::Epub => "epub",
+        }
+    }
+
+    /// File extension of the rendered output, used for cache paths.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::View | Self::Download => "html",
+            Self::Markdown => "md",
+            Self::Epub => "xhtml",
+        }
+    }
+
+    /// `Content-Type` header value for the rendered output.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::View | Self::Download => "text/html; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Epub => "application/xhtml+xml; charset=utf-8",
+        }
+    }
 }
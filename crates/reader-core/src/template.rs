@@ -1,14 +1,19 @@
 //! Final article template — askama wrapper + the public
-//! [`render_article`] entry point the pipeline calls.
+//! [`render_article`] entry point the pipeline calls, plus its EPUB
+//! chapter and Markdown counterparts [`render_chapter`] and
+//! [`render_markdown`].
 
 use std::time::Duration;
 
 use askama::Template;
 
 use crate::{
+    config::CONFIG,
     context::Context,
+    highlight,
+    image::embed_images,
     pipeline_error::PipelineError,
-    text_element::{Header, TextCompound},
+    text_element::{tidy_markdown, Header, TextCompound},
 };
 
 /// Upper bound on how long `render_article` will wait for a single
@@ -27,8 +32,18 @@ const HTML_BODY_CAPACITY_HINT: usize = 50_000;
 struct ArticleTemplate<'a> {
     url: &'a str,
     code: &'a str,
-    has_code: bool,
+    highlight_css: Option<&'a str>,
     download_link: Option<String>,
+    markdown_link: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "chapter.xhtml", escape = "html")]
+struct ChapterTemplate<'a> {
+    title: &'a str,
+    language: &'a str,
+    url: &'a str,
+    code: &'a str,
 }
 
 /// Build the `TextCompound` sequence that seeds the article body: the
@@ -46,14 +61,56 @@ fn article_header<'a>(ctx: &'a Context<'a>) -> [TextCompound<'a>; 2] {
 /// Compile a sequence of `TextCompound` parts into the final HTML
 /// response, wrapping it in the askama template at
 /// `templates/article.html`.
+pub fn render_article(parts: &[TextCompound], ctx: &mut Context) -> Result<String, PipelineError> {
+    let body = compile_body(parts, ctx);
+    let download_link = (!ctx.mode.is_download()).then(|| format!("/d/{}", ctx.min_id));
+    let markdown_link = (!ctx.mode.is_download()).then(|| format!("/md/{}", ctx.min_id));
+    let highlight_css = body
+        .contains(highlight::CODE_CLASS)
+        .then(|| highlight::DARK_STYLESHEET.as_str());
+    ArticleTemplate {
+        url: ctx.url.as_str(),
+        code: &body,
+        highlight_css,
+        download_link,
+        markdown_link,
+    }
+    .render()
+    .map_err(|e| PipelineError::Render(e.to_string()))
+}
+
+/// Compile a sequence of `TextCompound` parts into a standalone XHTML
+/// content document (`templates/chapter.xhtml`), ready for
+/// [`crate::epub::package`] to bundle.
+pub fn render_chapter(parts: &[TextCompound], ctx: &mut Context) -> Result<String, PipelineError> {
+    let body = compile_body(parts, ctx);
+    let title = ctx
+        .meta
+        .title
+        .as_deref()
+        .or(ctx.meta.html_title.as_deref())
+        .unwrap_or(ctx.url.as_str());
+    ChapterTemplate {
+        title,
+        // `und`: undetermined (BCP 47).
+        language: ctx.meta.language.as_deref().unwrap_or("und"),
+        url: ctx.url.as_str(),
+        code: &body,
+    }
+    .render()
+    .map_err(|e| PipelineError::Render(e.to_string()))
+}
+
+/// Run the HTML compiler over the article header and `parts`.
 ///
 /// Image re-encoding runs in parallel via the registered image
 /// backend. We collect every resulting [`crate::image::ImageTicket`]
 /// eagerly (so all workers are launched before we start blocking) and
-/// then wait on each one with a bounded timeout before serializing
-/// the template.
+/// then wait on each one with a bounded timeout before returning.
+/// Self-contained renders then inline their images so the output
+/// stands on its own.
 #[allow(clippy::needless_collect)]
-pub fn render_article(parts: &[TextCompound], ctx: &mut Context) -> Result<String, PipelineError> {
+fn compile_body(parts: &[TextCompound], ctx: &mut Context) -> String {
     let ctx_snapshot = ctx.clone();
     let header = article_header(&ctx_snapshot);
 
@@ -68,15 +125,34 @@ pub fn render_article(parts: &[TextCompound], ctx: &mut Context) -> Result<Strin
     for ticket in tickets {
         let _ = ticket.done.recv_timeout(IMAGE_WAIT_TIMEOUT);
     }
+    if ctx.mode.is_self_contained() {
+        body = embed_images(
+            body,
+            &ctx.pending_images,
+            CONFIG.download_image_budget,
+            ctx.mode.is_epub(),
+            ctx.fetcher.as_ref(),
+        );
+    }
+    body
+}
 
-    let download_link = (!ctx.mode.is_download()).then(|| format!("/d/{}", ctx.min_id));
-    let has_code = body.contains("<code>");
-    ArticleTemplate {
-        url: ctx.url.as_str(),
-        code: &body,
-        has_code,
-        download_link,
+/// Compile a sequence of `TextCompound` parts into a Markdown document:
+/// title heading, a link back to the source, the hero image, then the
+/// article body. No image workers are involved — Markdown keeps the
+/// original image URLs.
+pub fn render_markdown(parts: &[TextCompound], ctx: &mut Context) -> Result<String, PipelineError> {
+    let ctx_snapshot = ctx.clone();
+    let [title, image] = article_header(&ctx_snapshot);
+
+    let mut body = String::with_capacity(HTML_BODY_CAPACITY_HINT);
+    title.markdown(ctx, &mut body);
+    body.push_str(&format!("\n\n<{}>\n\n", ctx.url));
+    image.markdown(ctx, &mut body);
+    for part in parts {
+        part.markdown(ctx, &mut body);
     }
-    .render()
-    .map_err(|e| PipelineError::Render(e.to_string()))
+    let mut markdown = tidy_markdown(&body);
+    markdown.push('\n');
+    Ok(markdown)
 }
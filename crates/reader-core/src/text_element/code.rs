@@ -0,0 +1,39 @@
+/// Code as the page laid it out: a block standing on its own (`<pre>`)
+/// or a span running inside a sentence (`<code>`), with the language the
+/// page declared for it, if any.
+#[derive(Debug)]
+pub struct Code {
+    pub block: bool,
+    /// The hint as the page wrote it (`rust`, `py`, …), not normalised.
+    pub language: Option<String>,
+    /// The source lines, without their line endings.
+    pub lines: Vec<String>,
+}
+
+impl Code {
+    /// A code block. One trailing newline ends the last line rather than
+    /// starting an empty one, so it is dropped.
+    pub fn block(text: &str, language: Option<String>) -> Self {
+        let text = text.replace("\r\n", "\n");
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        Self {
+            block: true,
+            language,
+            lines: text.split('\n').map(str::to_owned).collect(),
+        }
+    }
+
+    /// Inline code.
+    pub fn inline(text: &str, language: Option<String>) -> Self {
+        Self {
+            block: false,
+            language,
+            lines: text.lines().map(str::to_owned).collect(),
+        }
+    }
+
+    /// The lines joined back into one string.
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+}
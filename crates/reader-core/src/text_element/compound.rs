@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 
-use super::{Header, Table};
+use super::{Code, Header, Image, Numbering, Table};
 
 /// Rich-text IR produced by the parser stage and consumed by the HTML
 /// template compiler.
@@ -31,8 +31,14 @@ pub enum TextCompound<'a> {
     Sup(Box<TextCompound<'a>>),
     Sub(Box<TextCompound<'a>>),
     Small(Box<TextCompound<'a>>),
-    Code(String),
-    Img(Cow<'a, str>),
+    Code(Code),
+    Img(Image<'a>),
+    /// A `<figure>` with its caption. Figures without a caption lower to
+    /// their content alone.
+    Figure {
+        content: Box<TextCompound<'a>>,
+        caption: Box<TextCompound<'a>>,
+    },
     Br,
     Heading {
         fragment_ids: Vec<Cow<'a, str>>,
@@ -42,6 +48,14 @@ pub enum TextCompound<'a> {
     P(Box<TextCompound<'a>>),
     Quote(Box<TextCompound<'a>>),
     Ul(Vec<TextCompound<'a>>),
+    /// Ordered list. `start` is the first item's number when the source
+    /// gives one; otherwise 1, or the item count when `reversed`.
+    Ol {
+        items: Vec<TextCompound<'a>>,
+        start: Option<i64>,
+        reversed: bool,
+        numbering: Numbering,
+    },
     Table(Table<'a>),
 }
 
@@ -52,9 +66,18 @@ impl<'a> TextCompound<'a> {
         Self::Raw(text.into())
     }
 
-    /// Construct an `Img` from a URL-like value.
+    /// Construct an `Img` from a URL-like value, with no alt text or
+    /// size.
     pub fn img(src: impl Into<Cow<'a, str>>) -> Self {
-        Self::Img(src.into())
+        Self::Img(Image::new(src))
+    }
+
+    /// Wrap `content` in a figure captioned by `caption`.
+    pub fn figure(content: Self, caption: Self) -> Self {
+        Self::Figure {
+            content: Box::new(content),
+            caption: Box::new(caption),
+        }
     }
 
     /// Wrap `content` in an anchor with the given href.
@@ -122,6 +145,23 @@ impl<'a> TextCompound<'a> {
         Self::Quote(Box::new(content))
     }
 
+    /// Whether `self` renders as a block of its own rather than a run of
+    /// inline text.
+    pub fn is_block(&self) -> bool {
+        match self {
+            Self::P(_)
+            | Self::Heading { .. }
+            | Self::Quote(_)
+            | Self::Ul(_)
+            | Self::Ol { .. }
+            | Self::Table(_)
+            | Self::Figure { .. } => true,
+            Self::Code(code) => code.block,
+            Self::Array(items) => items.iter().any(Self::is_block),
+            _ => false,
+        }
+    }
+
     /// Whether the first element of an `Array` is an `H1`. Used by the
     /// pipeline to decide whether to dedup the page title against the
     /// article's own leading heading.
@@ -21,6 +21,17 @@ impl Header {
             Self::H5 => "h5",
         }
     }
+
+    /// Nesting depth, `1` for `H1` through `5` for `H5`.
+    pub fn depth(&self) -> usize {
+        match self {
+            Self::H1 => 1,
+            Self::H2 => 2,
+            Self::H3 => 3,
+            Self::H4 => 4,
+            Self::H5 => 5,
+        }
+    }
 }
 
 impl FromStr for Header {
@@ -1,12 +1,11 @@
 //! Walk a [`TextCompound`] tree and emit the final article HTML fragment.
 
-use std::ops::Not;
-
 use crate::{
     cache::get_shortened_from_url,
     context::Context,
-    image::{get_image_url, ImageTicket},
-    text_element::TextCompound,
+    highlight::{highlight, CODE_CLASS},
+    image::{get_image_url, ImageTicket, PendingImage},
+    text_element::{CellKind, Table, TableCell, TextCompound},
     urls::is_html,
 };
 
@@ -47,27 +46,52 @@ impl<'a> TextCompound<'a> {
                 out.push_str("<br/>");
                 vec![]
             }
-            Self::Code(text) => {
-                if text.contains('\n') {
-                    push_container(out, "pre", |out| {
-                        push_container(out, "code", |out| {
-                            out.push_str(&html_escape::encode_text(text));
-                            vec![]
-                        })
-                    })
-                } else {
-                    push_container(out, "code", |out| {
-                        out.push_str(&html_escape::encode_text(text));
-                        out.push_str("&nbsp;");
+            Self::Code(code) => {
+                let text = code.text();
+                // The declared language labels the `<code>` the way pages
+                // write it, `class="language-rust"`.
+                let label = code
+                    .language
+                    .as_ref()
+                    .map(|language| ("class", format!("language-{}", language)));
+                if !code.block {
+                    return wrap_tag(out, "code", label, |out| {
+                        out.push_str(&html_escape::encode_text(&text));
+                        out.push_str("&#160;");
                         vec![]
-                    })
+                    });
                 }
+                let highlighted = highlight(&text, code.language.as_deref());
+                let class = highlighted.as_ref().map(|_| ("class", CODE_CLASS));
+                wrap_tag(out, "pre", class, |out| {
+                    wrap_tag(out, "code", label, |out| {
+                        match &highlighted {
+                            Some(spans) => out.push_str(spans),
+                            None => out.push_str(&html_escape::encode_text(&text)),
+                        }
+                        vec![]
+                    })
+                })
             }
-            Self::Img(src) => {
-                let resolved = get_image_url(src);
+            Self::Img(image) => {
+                let resolved = get_image_url(&image.src, &ctx.fetcher);
                 out.push_str("<img src=\"");
-                out.push_str(&resolved.url);
-                out.push_str("\">");
+                out.push_str(&html_escape::encode_double_quoted_attribute(&resolved.url));
+                out.push_str("\" alt=\"");
+                let alt = image.alt.as_deref().unwrap_or_default();
+                out.push_str(&html_escape::encode_double_quoted_attribute(alt));
+                out.push('"');
+                for (name, value) in [("width", image.width), ("height", image.height)] {
+                    if let Some(value) = value {
+                        out.push_str(&format!(" {}=\"{}\"", name, value));
+                    }
+                }
+                out.push_str(" />");
+                ctx.pending_images.push(PendingImage {
+                    src: resolved.url.clone(),
+                    original: image.src.to_string(),
+                    cache_path: resolved.cache_path.clone(),
+                });
                 resolved.ticket.map(|t| vec![t]).unwrap_or_default()
             }
             Self::Heading {
@@ -75,52 +99,72 @@ impl<'a> TextCompound<'a> {
                 level,
                 content,
             } => {
-                let rewritten_ids: Vec<_> = fragment_ids
+                let mut rewritten_ids: Vec<_> = fragment_ids
                     .iter()
                     .flat_map(|id| ctx.map.get(id.as_ref()))
-                    .map(|n| format!("#{}", n))
+                    .map(|n| n.to_string())
                     .collect();
-                let attr = rewritten_ids
-                    .is_empty()
-                    .not()
-                    .then(|| ("id".to_string(), rewritten_ids.join(" ")));
-                push_element(out, level.to_str(), attr, content, ctx)
+                // EPUB navigation links to every heading, so each one
+                // needs an id even if nothing in the article points at it.
+                if rewritten_ids.is_empty() && ctx.mode.is_epub() {
+                    ctx.count += 1;
+                    rewritten_ids.push(ctx.count.to_string());
+                }
+                // An element carries one id; any other anchor pointing at
+                // the heading gets an empty span of its own inside it.
+                let mut ids = rewritten_ids.into_iter();
+                let attr = ids.next().map(|id| ("id", id));
+                wrap_tag(out, level.to_str(), attr, |out| {
+                    for id in ids {
+                        wrap_tag(out, "span", [("id", id)], |_| ());
+                    }
+                    content.html(ctx, out)
+                })
+            }
+            Self::Ul(items) => push_container(out, "ul", |out| push_list_items(out, items, ctx)),
+            Self::Ol {
+                items,
+                start,
+                reversed,
+                numbering,
+            } => {
+                let attributes = [
+                    start.map(|start| ("start", start.to_string())),
+                    reversed.then(|| ("reversed", "reversed".to_owned())),
+                    numbering.type_attr().map(|kind| ("type", kind.to_owned())),
+                ];
+                wrap_tag(out, "ol", attributes.into_iter().flatten(), |out| {
+                    push_list_items(out, items, ctx)
+                })
             }
-            Self::Ul(items) => push_container(out, "il", |out| {
-                items
-                    .iter()
-                    .flat_map(|item| push_simple_element(out, "li", item, ctx))
-                    .collect()
-            }),
             Self::P(child) => push_simple_element(out, "p", child, ctx),
-            Self::Table(table) => push_container(out, "table", |out| {
-                table
-                    .rows
-                    .iter()
-                    .flat_map(|row| {
-                        push_container(out, "tr", |out| {
-                            row.cells
-                                .iter()
-                                .flat_map(|cell| {
-                                    push_simple_element(out, cell.html_tag(), cell.content(), ctx)
-                                })
-                                .collect()
-                        })
-                    })
-                    .collect()
+            Self::Table(table) => push_container(out, "table", |out| push_table(out, table, ctx)),
+            Self::Figure { content, caption } => push_container(out, "figure", |out| {
+                let mut tickets = content.html(ctx, out);
+                tickets.extend(push_simple_element(out, "figcaption", caption, ctx));
+                tickets
             }),
-            Self::Quote(child) => push_simple_element(out, "quote", child, ctx),
+            Self::Quote(child) => {
+                // `<quote>` is styled by the article template but isn't a
+                // real element, which XHTML content documents reject.
+                let tag = if ctx.mode.is_epub() {
+                    "blockquote"
+                } else {
+                    "quote"
+                };
+                push_simple_element(out, tag, child, ctx)
+            }
         }
     }
 }
 
 /// Decide what href to emit for a link. In View mode we route outbound
-/// HTTP(S) links through `/m/{short}` for one-click cleaning; in Download
-/// mode and for mailto / fragment / non-HTML links we pass the href
+/// HTTP(S) links through `/m/{short}` for one-click cleaning; in
+/// self-contained modes and for mailto / fragment / non-HTML links we pass the href
 /// through unchanged. A failed cache write is logged and falls back to the
 /// original href rather than propagating.
 fn rewrite_href(ctx: &Context, raw: &str) -> String {
-    let rewritable = !ctx.mode.is_download()
+    let rewritable = !ctx.mode.is_self_contained()
         && !raw.starts_with("mailto:")
         && !raw.starts_with('#')
         && is_html(raw);
@@ -147,6 +191,61 @@ fn push_simple_element(
     push_element::<String>(out, tag, None, child, ctx)
 }
 
+/// Write each item of a list as an `<li>`.
+fn push_list_items(
+    out: &mut String,
+    items: &[TextCompound],
+    ctx: &mut Context,
+) -> Vec<ImageTicket> {
+    items
+        .iter()
+        .flat_map(|item| push_simple_element(out, "li", item, ctx))
+        .collect()
+}
+
+/// Write the caption and rows of a table, each run of rows in its
+/// `<thead>` / `<tbody>` / `<tfoot>`.
+fn push_table(out: &mut String, table: &Table, ctx: &mut Context) -> Vec<ImageTicket> {
+    let mut tickets = match &table.caption {
+        Some(caption) => push_simple_element(out, "caption", caption, ctx),
+        None => vec![],
+    };
+    for rows in table.rows.chunk_by(|a, b| a.group == b.group) {
+        tickets.extend(push_container(out, rows[0].group.html_tag(), |out| {
+            rows.iter()
+                .flat_map(|row| {
+                    push_container(out, "tr", |out| {
+                        row.cells
+                            .iter()
+                            .flat_map(|cell| push_cell(out, cell, ctx))
+                            .collect()
+                    })
+                })
+                .collect()
+        }));
+    }
+    tickets
+}
+
+/// Write a `<th>` / `<td>` with its scope and spans.
+fn push_cell(out: &mut String, cell: &TableCell, ctx: &mut Context) -> Vec<ImageTicket> {
+    let scope = match cell.kind {
+        CellKind::Header(scope) => scope.map(|scope| ("scope", scope.as_str().to_owned())),
+        CellKind::Data => None,
+    };
+    let attributes = [
+        scope,
+        (cell.colspan > 1).then(|| ("colspan", cell.colspan.to_string())),
+        (cell.rowspan > 1).then(|| ("rowspan", cell.rowspan.to_string())),
+    ];
+    wrap_tag(
+        out,
+        cell.html_tag(),
+        attributes.into_iter().flatten(),
+        |out| cell.content.html(ctx, out),
+    )
+}
+
 /// Write `<tag>…</tag>` where the body is built by `build`. Returns
 /// whatever `build` returns.
 fn push_container(
@@ -169,21 +268,21 @@ fn push_element<T: Into<String>>(
 }
 
 /// Low-level primitive used by `push_element` and `push_container`: write
-/// the open tag (optionally with one attribute), run `build`, write the
-/// close tag.
-fn wrap_tag<T: Into<String>, R>(
+/// the open tag (with any attributes, values escaped), run `build`, write
+/// the close tag.
+fn wrap_tag<N: Into<String>, V: Into<String>, R>(
     out: &mut String,
     tag: &str,
-    attribute: Option<(T, T)>,
+    attributes: impl IntoIterator<Item = (N, V)>,
     build: impl FnOnce(&mut String) -> R,
 ) -> R {
     out.push('<');
     out.push_str(tag);
-    if let Some((name, value)) = attribute {
+    for (name, value) in attributes {
         out.push(' ');
         out.push_str(&name.into());
         out.push_str("=\"");
-        out.push_str(&value.into());
+        out.push_str(&html_escape::encode_double_quoted_attribute(&value.into()));
         out.push('"');
     }
     out.push('>');
@@ -193,3 +292,30 @@ fn wrap_tag<T: Into<String>, R>(
     out.push_str("> ");
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::{http::FixtureFetcher, render_mode::RenderMode, text_element::Header};
+
+    #[test]
+    fn a_heading_carries_one_id_and_spans_for_the_rest() {
+        let mut ctx = Context {
+            url: "https://example.com/post".parse().unwrap(),
+            base: "https://example.com/post".parse().unwrap(),
+            mode: RenderMode::Epub,
+            min_id: String::new(),
+            map: HashMap::from([("a", 1), ("b", 2)]),
+            count: 2,
+            meta: Default::default(),
+            fetcher: Arc::new(FixtureFetcher::default()),
+            pending_images: Vec::new(),
+        };
+        let heading = TextCompound::heading(Header::H2, ["a", "b"], TextCompound::raw("Intro"));
+        let mut out = String::new();
+        heading.html(&mut ctx, &mut out);
+        assert_eq!(out, "<h2 id=\"1\"><span id=\"2\"></span> Intro</h2> ");
+    }
+}
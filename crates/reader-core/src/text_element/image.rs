@@ -0,0 +1,37 @@
+use std::borrow::Cow;
+
+/// An `<img>`: its resolved source plus what the page said about it. The
+/// alt text is the image's explanation for screen readers and for
+/// exports where it can't be shown; the intrinsic size lets the browser
+/// reserve space before the image arrives.
+#[derive(Debug)]
+pub struct Image<'a> {
+    pub src: Cow<'a, str>,
+    pub alt: Option<Cow<'a, str>>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl<'a> Image<'a> {
+    /// An image known only by its URL.
+    pub fn new(src: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            src: src.into(),
+            alt: None,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+/// Parse a `width` / `height` attribute: a pixel count, optionally
+/// suffixed `px`. Percentages and other units give `None`.
+pub(super) fn parse_dimension(value: &str) -> Option<u32> {
+    let value = value.trim();
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .parse()
+        .ok()
+        .filter(|&pixels| pixels > 0)
+}
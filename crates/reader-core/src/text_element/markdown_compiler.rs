@@ -0,0 +1,599 @@
+//! Walk a [`TextCompound`] tree and emit CommonMark (with GFM tables).
+//!
+//! Block-level nodes surround themselves with blank lines and inline
+//! nodes write straight into the buffer; [`tidy`] collapses the extra
+//! blank lines at the end. Nested blocks (list items, quotes) are
+//! rendered into a scratch buffer first and then re-indented.
+
+use crate::{context::Context, text_element::TextCompound};
+
+const PUNCTUATION: &str = ".,;:!?()[]{}";
+
+/// Characters with inline meaning in Markdown that must be backslash
+/// escaped when they appear in plain text.
+const MARKDOWN_SPECIAL: &str = "\\`*_[]<>|";
+
+/// Characters that open a heading, a list item, a thematic break or a
+/// setext underline when they begin a line.
+const LINE_START_SPECIAL: &str = "#-+=";
+
+/// Characters that would end or split a link destination, or be read as
+/// an escape inside it, so are percent-encoded there.
+const DESTINATION_SPECIAL: &str = "()<>\\";
+
+/// The largest ordered list marker CommonMark accepts: nine digits.
+const MAX_LIST_MARKER: i64 = 999_999_999;
+
+impl<'a> TextCompound<'a> {
+    /// Render `self` as Markdown into `out`. Unlike [`TextCompound::html`]
+    /// this never launches image workers — images keep their original
+    /// URLs, since a Markdown file is read away from this server.
+    pub fn markdown(&'a self, ctx: &mut Context, out: &mut String) {
+        match self {
+            Self::Raw(text) => {
+                let starts_with_punct =
+                    text.chars().next().is_some_and(|c| PUNCTUATION.contains(c));
+                if starts_with_punct && out.ends_with(' ') {
+                    out.pop();
+                }
+                push_escaped(out, text);
+            }
+            Self::Link { content, href } => {
+                out.push('[');
+                content.markdown(ctx, out);
+                out.push_str("](");
+                push_destination(out, href);
+                out.push(')');
+            }
+            Self::Italic(child) => wrap_inline(out, "*", child, ctx),
+            Self::Bold(child) => wrap_inline(out, "**", child, ctx),
+            Self::Underline(child) | Self::Small(child) => child.markdown(ctx, out),
+            Self::Abbr { content, .. } => content.markdown(ctx, out),
+            Self::Sup(child) => wrap_html(out, "sup", child, ctx),
+            Self::Sub(child) => wrap_html(out, "sub", child, ctx),
+            Self::Array(items) => items.iter().for_each(|item| item.markdown(ctx, out)),
+            Self::Br => out.push_str("  \n"),
+            Self::Code(code) if code.block => {
+                let text = code.text();
+                let fence = "`".repeat(longest_backtick_run(&text).max(2) + 1);
+                push_block(out, |out| {
+                    out.push_str(&fence);
+                    // The info string labels the fence with its language.
+                    out.push_str(code.language.as_deref().unwrap_or_default());
+                    out.push('\n');
+                    out.push_str(&text);
+                    out.push('\n');
+                    out.push_str(&fence);
+                });
+            }
+            Self::Code(code) => {
+                // A code span can't hold a line break; its lines run on.
+                let text = code.lines.join(" ");
+                let fence = "`".repeat(longest_backtick_run(&text) + 1);
+                out.push_str(&fence);
+                out.push(' ');
+                out.push_str(&text);
+                out.push(' ');
+                out.push_str(&fence);
+            }
+            Self::Img(image) => {
+                if !image.src.is_empty() {
+                    out.push_str("![");
+                    push_escaped(out, image.alt.as_deref().unwrap_or_default());
+                    out.push_str("](");
+                    push_destination(out, &image.src);
+                    out.push(')');
+                }
+            }
+            Self::Figure { content, caption } => {
+                let mut text = String::new();
+                content.markdown(ctx, &mut text);
+                push_block(out, |out| out.push_str(text.trim()));
+                // Markdown has no captions; an italic line under the
+                // figure is the closest reading.
+                let mut text = String::new();
+                wrap_inline(&mut text, "*", caption, ctx);
+                push_block(out, |out| out.push_str(text.trim()));
+            }
+            Self::Heading { level, content, .. } => {
+                let mut text = String::new();
+                content.markdown(ctx, &mut text);
+                let text = single_line(&text);
+                if text.is_empty() {
+                    return;
+                }
+                push_block(out, |out| {
+                    out.push_str(&"#".repeat(level.depth()));
+                    out.push(' ');
+                    out.push_str(&text);
+                });
+            }
+            Self::P(child) => {
+                let mut text = String::new();
+                child.markdown(ctx, &mut text);
+                push_block(out, |out| out.push_str(text.trim()));
+            }
+            Self::Quote(child) => {
+                let mut text = String::new();
+                child.markdown(ctx, &mut text);
+                push_block(out, |out| {
+                    out.push_str(&prefix_lines(&tidy(&text), "> ", "> "))
+                });
+            }
+            Self::Ul(items) => push_list(out, items, |_| "- ".to_owned(), ctx),
+            Self::Ol {
+                items,
+                start,
+                reversed,
+                ..
+            } => {
+                // Markdown only numbers in decimal, counting up from the
+                // first marker, so the markers are written out in full: a
+                // reversed list at least reads right as plain text. Markers
+                // must be 0 to nine digits; a list counting past either
+                // end falls back to bullets.
+                let len = items.len() as i64;
+                let first = start.unwrap_or(if *reversed { len } else { 1 });
+                let step = if *reversed { -1 } else { 1 };
+                let last = first.saturating_add(step * (len - 1).max(0));
+                if [first, last]
+                    .iter()
+                    .all(|marker| (0..=MAX_LIST_MARKER).contains(marker))
+                {
+                    push_list(
+                        out,
+                        items,
+                        |index| format!("{}. ", first + step * index as i64),
+                        ctx,
+                    );
+                } else {
+                    push_list(out, items, |_| "- ".to_owned(), ctx);
+                }
+            }
+            Self::Table(table) => {
+                // GFM has no spans: a spanning cell fills its first slot
+                // and leaves the rest it covers empty.
+                let rows: Vec<Vec<String>> = table
+                    .grid()
+                    .into_iter()
+                    .map(|row| {
+                        row.into_iter()
+                            .map(|slot| {
+                                let mut text = String::new();
+                                if let Some(cell) = slot {
+                                    cell.content.markdown(ctx, &mut text);
+                                }
+                                table_cell(&text)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let width = rows.first().map_or(0, Vec::len);
+                if width == 0 {
+                    return;
+                }
+                // Nor captions; an italic line over the table stands in.
+                if let Some(caption) = &table.caption {
+                    let mut text = String::new();
+                    wrap_inline(&mut text, "*", caption, ctx);
+                    push_block(out, |out| out.push_str(text.trim()));
+                }
+                push_block(out, |out| {
+                    push_table_row(out, &rows[0]);
+                    push_table_row(out, &vec!["---".to_owned(); width]);
+                    rows[1..].iter().for_each(|row| push_table_row(out, row));
+                });
+            }
+        }
+    }
+}
+
+/// Collapse the blank-line padding left by [`push_block`]: no more than
+/// one empty line in a row, and none at either end. Lines inside code
+/// fences are kept verbatim.
+pub fn tidy(markdown: &str) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut blank_run = 0;
+    // Length of the open fence; only a fence at least as long closes it.
+    let mut fence = None;
+    for line in markdown.trim().lines() {
+        match (fence, fence_length(line)) {
+            (None, Some(length)) => fence = Some(length),
+            (Some(open), Some(length)) if length >= open && line.trim().len() == length => {
+                fence = None
+            }
+            (Some(_), _) => {
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            }
+            (None, None) => {}
+        }
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run == 1 {
+                out.push('\n');
+            }
+            continue;
+        }
+        blank_run = 0;
+        // Two trailing spaces are a hard line break; anything else is noise.
+        out.push_str(if line.ends_with("  ") {
+            line
+        } else {
+            line.trim_end()
+        });
+        out.push('\n');
+    }
+    out.trim_end().to_owned()
+}
+
+/// Length of the backtick fence `line` opens or closes, if it is one.
+fn fence_length(line: &str) -> Option<usize> {
+    let line = line.trim_start();
+    let length = line.len() - line.trim_start_matches('`').len();
+    (length >= 3 && !line[length..].contains('`')).then_some(length)
+}
+
+fn push_escaped(out: &mut String, text: &str) {
+    let mut last_was_space = out.ends_with(char::is_whitespace);
+    let mut line_start = out
+        .rsplit('\n')
+        .next()
+        .is_some_and(|line| line.trim().is_empty());
+    // Digits opening the line, which a `.` or `)` would turn into an
+    // ordered list marker.
+    let mut digits = 0;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_was_space {
+                out.push(' ');
+            }
+            last_was_space = true;
+            digits = 0;
+            continue;
+        }
+        last_was_space = false;
+        if MARKDOWN_SPECIAL.contains(c)
+            || (line_start && LINE_START_SPECIAL.contains(c))
+            || (matches!(c, '.' | ')') && (1..=9).contains(&digits))
+        {
+            out.push('\\');
+        }
+        out.push(c);
+        digits = if (line_start || digits > 0) && c.is_ascii_digit() {
+            digits + 1
+        } else {
+            0
+        };
+        line_start = false;
+    }
+}
+
+/// Write a link or image destination, percent-encoding whitespace,
+/// control characters and [`DESTINATION_SPECIAL`] so the URL can't end
+/// the link early or span lines.
+fn push_destination(out: &mut String, url: &str) {
+    for c in url.chars() {
+        if c.is_whitespace() || c.is_control() || DESTINATION_SPECIAL.contains(c) {
+            let mut utf8 = [0; 4];
+            for byte in c.encode_utf8(&mut utf8).bytes() {
+                out.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+/// Write a block-level element on its own paragraph.
+fn push_block(out: &mut String, build: impl FnOnce(&mut String)) {
+    out.push_str("\n\n");
+    build(out);
+    out.push_str("\n\n");
+}
+
+/// Write a list block, each item under its `marker` with continuation
+/// lines indented to match.
+fn push_list(
+    out: &mut String,
+    items: &[TextCompound],
+    marker: impl Fn(usize) -> String,
+    ctx: &mut Context,
+) {
+    push_block(out, |out| {
+        for (index, item) in items.iter().enumerate() {
+            let mut text = String::new();
+            item.markdown(ctx, &mut text);
+            let marker = marker(index);
+            let indent = " ".repeat(marker.len());
+            out.push_str(&prefix_lines(&tidy(&text), &marker, &indent));
+            out.push('\n');
+        }
+    });
+}
+
+fn wrap_inline(out: &mut String, marker: &str, child: &TextCompound, ctx: &mut Context) {
+    let mut text = String::new();
+    child.markdown(ctx, &mut text);
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        out.push_str(&text);
+        return;
+    }
+    // Emphasis markers must hug the text, so move any padding outside.
+    if text.starts_with(' ') && !out.ends_with(' ') {
+        out.push(' ');
+    }
+    out.push_str(marker);
+    out.push_str(trimmed);
+    out.push_str(marker);
+    if text.ends_with(' ') {
+        out.push(' ');
+    }
+}
+
+fn wrap_html(out: &mut String, tag: &str, child: &TextCompound, ctx: &mut Context) {
+    out.push('<');
+    out.push_str(tag);
+    out.push('>');
+    child.markdown(ctx, out);
+    out.push_str("</");
+    out.push_str(tag);
+    out.push('>');
+}
+
+/// Prefix the first line of `text` with `first` and every following
+/// line with `rest`. Used for list bullets and blockquote markers.
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (index, line) in text.lines().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        let prefix = if index == 0 { first } else { rest };
+        if line.is_empty() {
+            out.push_str(prefix.trim_end());
+        } else {
+            out.push_str(prefix);
+            out.push_str(line);
+        }
+    }
+    if out.is_empty() {
+        out.push_str(first.trim_end());
+    }
+    out
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// GFM table cells can't contain newlines or bare pipes.
+fn table_cell(text: &str) -> String {
+    tidy(text)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+fn push_table_row(out: &mut String, cells: &[String]) {
+    out.push('|');
+    for cell in cells {
+        out.push(' ');
+        out.push_str(cell);
+        out.push_str(" |");
+    }
+    out.push('\n');
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::{
+        http::FixtureFetcher,
+        render_mode::RenderMode,
+        text_element::{CellKind, Code, Header, Image, Row, RowGroup, Table, TableCell},
+    };
+
+    fn render(node: &TextCompound) -> String {
+        let mut ctx = Context {
+            url: "https://example.com/post".parse().unwrap(),
+            base: "https://example.com/post".parse().unwrap(),
+            mode: RenderMode::Markdown,
+            min_id: String::new(),
+            map: HashMap::new(),
+            count: 0,
+            meta: Default::default(),
+            fetcher: Arc::new(FixtureFetcher::default()),
+            pending_images: Vec::new(),
+        };
+        let mut out = String::new();
+        node.markdown(&mut ctx, &mut out);
+        tidy(&out)
+    }
+
+    #[test]
+    fn renders_blocks_and_inline_markup() {
+        let doc = TextCompound::Array(vec![
+            TextCompound::heading(Header::H2, [] as [&str; 0], TextCompound::raw("Intro")),
+            TextCompound::paragraph(TextCompound::Array(vec![
+                TextCompound::raw("See "),
+                TextCompound::link(TextCompound::raw("the docs"), "https://example.com/docs"),
+                TextCompound::raw(", then "),
+                TextCompound::bold(TextCompound::raw("run_it")),
+            ])),
+            TextCompound::Ul(vec![TextCompound::raw("one"), TextCompound::raw("two")]),
+        ]);
+        assert_eq!(
+            render(&doc),
+            "## Intro\n\nSee [the docs](https://example.com/docs), then **run\\_it**\n\n- one\n- two"
+        );
+    }
+
+    #[test]
+    fn numbers_ordered_and_nested_lists() {
+        let doc = TextCompound::Ol {
+            items: vec![
+                TextCompound::raw("Mix"),
+                TextCompound::Array(vec![
+                    TextCompound::raw("Knead"),
+                    TextCompound::Ul(vec![TextCompound::raw("ten minutes")]),
+                ]),
+                TextCompound::raw("Bake"),
+            ],
+            start: None,
+            reversed: true,
+            numbering: Default::default(),
+        };
+        assert_eq!(
+            render(&doc),
+            "3. Mix\n2. Knead\n\n   - ten minutes\n1. Bake"
+        );
+    }
+
+    #[test]
+    fn reversed_list_below_zero_falls_back_to_bullets() {
+        let list = |start| TextCompound::Ol {
+            items: vec![
+                TextCompound::raw("a"),
+                TextCompound::raw("b"),
+                TextCompound::raw("c"),
+            ],
+            start: Some(start),
+            reversed: true,
+            numbering: Default::default(),
+        };
+        assert_eq!(render(&list(2)), "2. a\n1. b\n0. c");
+        assert_eq!(render(&list(1)), "- a\n- b\n- c");
+    }
+
+    #[test]
+    fn destinations_cannot_break_out_of_the_link() {
+        let doc = TextCompound::paragraph(TextCompound::Array(vec![
+            TextCompound::link(
+                TextCompound::raw("wiki"),
+                "https://example.com/a_(b) c\n<d>",
+            ),
+            TextCompound::Img(Image::new("https://example.com/x).png")),
+        ]));
+        assert_eq!(
+            render(&doc),
+            "[wiki](https://example.com/a_%28b%29%20c%0A%3Cd%3E)![](https://example.com/x%29.png)"
+        );
+    }
+
+    #[test]
+    fn figure_keeps_alt_text_and_caption() {
+        let doc = TextCompound::figure(
+            TextCompound::Img(Image {
+                alt: Some("Rainfall [mm] by month".into()),
+                width: Some(640),
+                ..Image::new("https://example.com/rain.png")
+            }),
+            TextCompound::raw("Rainfall peaks in November."),
+        );
+        assert_eq!(
+            render(&doc),
+            "![Rainfall \\[mm\\] by month](https://example.com/rain.png)\n\n*Rainfall peaks in November.*"
+        );
+    }
+
+    #[test]
+    fn labels_code_fences_and_keeps_inline_code_inline() {
+        let doc = TextCompound::Array(vec![
+            TextCompound::paragraph(TextCompound::Array(vec![
+                TextCompound::raw("Call "),
+                TextCompound::Code(Code::inline("main()", None)),
+            ])),
+            TextCompound::Code(Code::block("x = 1\n", Some("py".into()))),
+        ]);
+        assert_eq!(render(&doc), "Call ` main() `\n\n```py\nx = 1\n```");
+    }
+
+    #[test]
+    fn lays_spanning_cells_out_on_the_grid() {
+        let cell = |kind, text, colspan, rowspan| TableCell {
+            kind,
+            content: TextCompound::raw(text),
+            colspan,
+            rowspan,
+        };
+        let row = |group, cells| Row { group, cells };
+        let doc = TextCompound::Table(Table {
+            caption: Some(Box::new(TextCompound::raw("Opening hours"))),
+            rows: vec![
+                row(
+                    RowGroup::Head,
+                    vec![
+                        cell(CellKind::Header(None), "Day", 1, 1),
+                        cell(CellKind::Header(None), "Hours", 2, 1),
+                    ],
+                ),
+                row(
+                    RowGroup::Body,
+                    vec![
+                        cell(CellKind::Data, "Mon-Fri", 1, 2),
+                        cell(CellKind::Data, "9", 1, 1),
+                        cell(CellKind::Data, "17", 1, 1),
+                    ],
+                ),
+                row(
+                    RowGroup::Body,
+                    vec![
+                        cell(CellKind::Data, "10", 1, 1),
+                        cell(CellKind::Data, "16", 1, 1),
+                    ],
+                ),
+            ],
+        });
+        assert_eq!(
+            render(&doc),
+            "*Opening hours*\n\n| Day | Hours |  |\n| --- | --- | --- |\n| Mon-Fri | 9 | 17 |\n|  | 10 | 16 |"
+        );
+    }
+
+    #[test]
+    fn tidy_keeps_blank_lines_inside_code_fences() {
+        let md = "\n\n\n```\nfn a() {}\n\n\nfn b() {}\n```\n\n\n\ntext\n\n";
+        assert_eq!(tidy(md), "```\nfn a() {}\n\n\nfn b() {}\n```\n\ntext");
+    }
+
+    #[test]
+    fn tidy_only_closes_a_fence_with_one_as_long() {
+        let md = "````md\n```\n\n\nquoted\n```\n````\n\n\ntext";
+        assert_eq!(tidy(md), "````md\n```\n\n\nquoted\n```\n````\n\ntext");
+    }
+
+    #[test]
+    fn escapes_block_syntax_at_line_start() {
+        let paragraphs = [
+            "# not a heading",
+            "- not a list",
+            "+ not a list",
+            "1984. Not a list either",
+            "> not a quote",
+            "--- not a rule",
+            "Mid-line #1 - 2. + fine",
+        ];
+        let doc = TextCompound::Array(
+            paragraphs
+                .iter()
+                .map(|text| TextCompound::paragraph(TextCompound::raw(*text)))
+                .collect(),
+        );
+        assert_eq!(
+            render(&doc),
+            "\\# not a heading\n\n\\- not a list\n\n\\+ not a list\n\n1984\\. Not a list either\n\n\
+             \\> not a quote\n\n\\--- not a rule\n\nMid-line #1 - 2. + fine"
+        );
+    }
+}
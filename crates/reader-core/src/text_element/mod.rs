@@ -1,13 +1,21 @@
+mod code;
 mod compound;
 mod header;
 mod html_compiler;
+mod image;
+mod markdown_compiler;
+mod numbering;
 mod parser;
 mod row;
 mod table;
 mod table_cell;
 
+pub use code::Code;
 pub use compound::TextCompound;
 pub use header::Header;
-pub use row::Row;
+pub use image::Image;
+pub use markdown_compiler::tidy as tidy_markdown;
+pub use numbering::Numbering;
+pub use row::{Row, RowGroup};
 pub use table::Table;
-pub use table_cell::TableCell;
+pub use table_cell::{CellKind, Scope, TableCell};
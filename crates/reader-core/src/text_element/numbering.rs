@@ -0,0 +1,42 @@
+use std::str::FromStr;
+
+/// Marker style of an ordered list, from its `type` attribute.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Numbering {
+    #[default]
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+}
+
+impl Numbering {
+    /// The `type` attribute value, `None` for the default decimal
+    /// numbering so it needn't be written out.
+    pub fn type_attr(&self) -> Option<&'static str> {
+        match self {
+            Self::Decimal => None,
+            Self::LowerAlpha => Some("a"),
+            Self::UpperAlpha => Some("A"),
+            Self::LowerRoman => Some("i"),
+            Self::UpperRoman => Some("I"),
+        }
+    }
+}
+
+impl FromStr for Numbering {
+    type Err = &'static str;
+
+    /// Parse a `type` attribute. Case matters: `a` and `A` differ.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            "1" => Self::Decimal,
+            "a" => Self::LowerAlpha,
+            "A" => Self::UpperAlpha,
+            "i" => Self::LowerRoman,
+            "I" => Self::UpperRoman,
+            _ => return Err("Invalid list numbering"),
+        })
+    }
+}
@@ -1,15 +1,18 @@
 //! DOM-to-`TextCompound` lowering. Consumes a pruned [`HTMLNode`] tree and
 //! produces the rich-text IR used by the HTML/template stages.
 
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 
 use crate::{
     context::Context,
+    highlight,
     html_node::HTMLNode,
     urls::{canonical_tag, extract_image_src},
 };
 
-use super::{Row, Table, TableCell, TextCompound};
+use super::{
+    image::parse_dimension, CellKind, Code, Image, Row, RowGroup, Table, TableCell, TextCompound,
+};
 
 impl<'a> TextCompound<'a> {
     /// Flatten `self` into plain text. Used for heading dedup against the
@@ -17,7 +20,7 @@ impl<'a> TextCompound<'a> {
     pub fn text(&'a self) -> Cow<'a, str> {
         match self {
             Self::Raw(text) => Cow::Borrowed(text),
-            Self::Code(text) => Cow::Borrowed(text),
+            Self::Code(code) => Cow::Owned(code.text()),
             Self::Link { content, .. }
             | Self::Abbr { content, .. }
             | Self::Heading { content, .. } => content.text(),
@@ -29,15 +32,27 @@ impl<'a> TextCompound<'a> {
             | Self::Small(child)
             | Self::P(child)
             | Self::Quote(child) => child.text(),
-            Self::Array(items) | Self::Ul(items) => {
+            Self::Array(items) | Self::Ul(items) | Self::Ol { items, .. } => {
                 Cow::Owned(items.iter().map(|item| item.text()).collect::<String>())
             }
-            Self::Img(_) | Self::Br => Cow::Borrowed(""),
+            Self::Img(image) => Cow::Borrowed(image.alt.as_deref().unwrap_or("")),
+            Self::Figure { content, caption } => {
+                Cow::Owned(format!("{}{}", content.text(), caption.text()))
+            }
+            Self::Br => Cow::Borrowed(""),
             Self::Table(table) => Cow::Owned(
                 table
-                    .rows
-                    .iter()
-                    .flat_map(|row| row.cells.iter().map(|cell| cell.content().text()))
+                    .caption
+                    .as_deref()
+                    .into_iter()
+                    .chain(
+                        table
+                            .rows
+                            .iter()
+                            .flat_map(|row| &row.cells)
+                            .map(|cell| &cell.content),
+                    )
+                    .map(|content| content.text())
                     .collect::<String>(),
             ),
         }
@@ -72,16 +87,17 @@ impl<'a> TextCompound<'a> {
             "div" | "section" | "main" | "article" | "html" | "body" | "document" => {
                 Self::from_array(ctx, children)
             }
-            "table" => Some(Self::Table(lower_table(ctx, node))),
+            "table" => lower_table(ctx, node),
             "time" => Self::from_array(ctx, children).map(Self::paragraph),
             "p" => {
                 let is_code_block = attrs
                     .get("class")
                     .is_some_and(|class| class.contains("code"));
                 if is_code_block {
-                    let mut text = node.get_text();
-                    text.push('\n');
-                    Some(Self::Code(text))
+                    Some(Self::Code(Code::block(
+                        &node.get_text(),
+                        code_language(node),
+                    )))
                 } else {
                     Self::from_array(ctx, children).map(Self::paragraph)
                 }
@@ -94,7 +110,7 @@ impl<'a> TextCompound<'a> {
                 None => Self::from_array(ctx, children),
             },
             "u" => Self::from_array(ctx, children).map(Self::underline),
-            "i" | "em" => Self::from_array(ctx, children).map(Self::italic),
+            "i" | "em" | "cite" => Self::from_array(ctx, children).map(Self::italic),
             "b" | "strong" => Self::from_array(ctx, children).map(Self::bold),
             "br" | "wbr" | "hr" => Some(Self::Br),
             "small" => Self::from_array(ctx, children).map(Self::small),
@@ -103,15 +119,21 @@ impl<'a> TextCompound<'a> {
                 let title = attrs.get("title").map(String::as_str).unwrap_or("");
                 Self::from_array(ctx, children).map(|body| Self::abbr(body, title))
             }
-            "ul" | "ol" => Some(Self::Ul(
-                children
-                    .iter()
-                    .filter_map(|item| Self::from_array(ctx, item.children()?))
-                    .collect(),
-            )),
+            "ul" => Some(Self::Ul(lower_list_items(ctx, children)?)),
+            "ol" => Some(Self::Ol {
+                items: lower_list_items(ctx, children)?,
+                start: attrs.get("start").and_then(|n| n.trim().parse().ok()),
+                reversed: attrs.contains_key("reversed"),
+                numbering: attrs
+                    .get("type")
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or_default(),
+            }),
+            // A list item whose list was pruned away.
+            "li" => Self::from_array(ctx, children),
             "sub" => Self::from_array(ctx, children).map(Self::sub),
             "sup" => Self::from_array(ctx, children).map(Self::sup),
-            "img" => extract_image_src(ctx, attrs).map(Self::img),
+            "img" => lower_image(ctx, attrs).map(Self::Img),
             heading_tag @ ("h1" | "h2" | "h3" | "h4" | "h5") => {
                 let body = Self::from_array(ctx, children)?;
                 // Drop a heading whose text matches the page title — we
@@ -130,14 +152,25 @@ impl<'a> TextCompound<'a> {
                     .unwrap_or_default();
                 Some(Self::heading(heading_tag.parse().ok()?, fragment_ids, body))
             }
-            "figure" | "figcaption" => {
-                // Prefer the `<figcaption>` child if one is present as the
-                // last element; otherwise fall back to the figure body.
-                let target = figcaption_children(children).unwrap_or(children);
-                Self::from_array(ctx, target).map(Self::quote)
-            }
+            "figure" => lower_figure(ctx, children),
+            "figcaption" => Self::from_array(ctx, children).map(Self::quote),
             "quote" | "blockquote" => Self::from_array(ctx, children).map(Self::quote),
-            "cite" | "code" | "pre" => Some(Self::Code(node.get_text())),
+            "pre" => Some(Self::Code(Code::block(
+                &node.get_text(),
+                code_language(node),
+            ))),
+            // Bare `<code>` is inline, unless it spans several lines:
+            // some pages style it as a block rather than wrap it in a
+            // `<pre>`.
+            "code" => {
+                let text = node.get_text();
+                let language = code_language(node);
+                Some(Self::Code(if text.trim_end().contains('\n') {
+                    Code::block(&text, language)
+                } else {
+                    Code::inline(&text, language)
+                }))
+            }
             "math" => None, // not supported yet
             unknown => {
                 eprintln!("unsupported element <{}>", unknown);
@@ -147,39 +180,217 @@ impl<'a> TextCompound<'a> {
     }
 }
 
+/// Lower the children of a `<ul>` / `<ol>` into list items, or `None`
+/// if none survive. A list nested directly in another (`<ul><li>a</li>
+/// <ul>…</ul></ul>`, common in the wild) is kept as a sub-list of the
+/// item before it rather than flattened into its parent.
+fn lower_list_items<'a>(
+    ctx: &mut Context<'a>,
+    children: &'a [HTMLNode],
+) -> Option<Vec<TextCompound<'a>>> {
+    let mut items: Vec<TextCompound<'a>> = Vec::new();
+    for child in children {
+        let Some(lowered) = TextCompound::from_node(ctx, child) else {
+            continue;
+        };
+        let is_sublist = matches!(child.get_tag_name(), Some("ul" | "ol"));
+        match items.pop() {
+            Some(TextCompound::Array(mut parts)) if is_sublist => {
+                parts.push(lowered);
+                items.push(TextCompound::Array(parts));
+            }
+            Some(previous) if is_sublist => {
+                items.push(TextCompound::Array(vec![previous, lowered]));
+            }
+            previous => {
+                items.extend(previous);
+                items.push(lowered);
+            }
+        }
+    }
+    (!items.is_empty()).then_some(items)
+}
+
+/// The language a code element declares with a `language-…` or `lang-…`
+/// class, on itself or on the `<code>` inside it (`<pre><code
+/// class="language-rust">`). Read from the copy
+/// [`highlight::tag_code_languages`] made when Readability has dropped
+/// the class.
+fn code_language(node: &HTMLNode) -> Option<String> {
+    let declared = |node: &HTMLNode| match node {
+        HTMLNode::Element { attrs, .. } => attrs
+            .get(highlight::LANGUAGE_ATTR)
+            .map(String::as_str)
+            .or_else(|| highlight::class_language(attrs.get("class")?))
+            .map(str::to_owned),
+        HTMLNode::Text(_) => None,
+    };
+    declared(node).or_else(|| {
+        node.children()?
+            .iter()
+            .filter(|child| child.get_tag_name() == Some("code"))
+            .find_map(declared)
+    })
+}
+
 /// Lower a `<table>` into the `Table { rows: Vec<Row { cells: … }> }`
-/// hierarchy. Rows with no lowered cells are kept as empty rows so the
-/// grid retains its shape.
-fn lower_table<'a>(ctx: &mut Context<'a>, table_node: &'a HTMLNode) -> Table<'a> {
-    let rows = table_node
-        .select(&["tr"])
-        .iter()
-        .map(|row_node| Row {
-            cells: row_node
-                .select(&["td", "th"])
-                .iter()
-                .filter_map(|cell_node| lower_cell(ctx, cell_node))
-                .collect(),
+/// hierarchy, from its own caption and rows only: a table nested in a
+/// cell is lowered as part of that cell's content. Rows with no cells
+/// are kept as empty rows so the grid retains its shape. A layout-only
+/// table (see [`Table::is_layout`]) comes out as its cells' content,
+/// one paragraph each.
+fn lower_table<'a>(ctx: &mut Context<'a>, table_node: &'a HTMLNode) -> Option<TextCompound<'a>> {
+    let mut caption = None;
+    let mut rows = Vec::new();
+    for child in table_node.children()? {
+        match child.get_tag_name() {
+            Some("caption") => {
+                caption = caption
+                    .or_else(|| TextCompound::from_array(ctx, child.children()?).map(Box::new))
+            }
+            Some("thead") => lower_row_group(ctx, child, RowGroup::Head, &mut rows),
+            Some("tbody") => lower_row_group(ctx, child, RowGroup::Body, &mut rows),
+            Some("tfoot") => lower_row_group(ctx, child, RowGroup::Foot, &mut rows),
+            Some("tr") => rows.push(lower_row(ctx, child, RowGroup::Body, 1)),
+            _ => {}
+        }
+    }
+    let table = Table { caption, rows };
+    if !table.is_layout() {
+        return Some(TextCompound::Table(table));
+    }
+    let mut paragraphs: Vec<TextCompound<'a>> = table
+        .rows
+        .into_iter()
+        .flat_map(|row| row.cells)
+        .filter_map(|cell| match cell.content {
+            TextCompound::Array(items) if items.is_empty() => None,
+            content if content.is_block() => Some(content),
+            content => Some(TextCompound::paragraph(content)),
         })
         .collect();
-    Table { rows }
+    if paragraphs.len() <= 1 {
+        paragraphs.pop()
+    } else {
+        Some(TextCompound::Array(paragraphs))
+    }
+}
+
+/// Lower the `<tr>`s of a `<thead>` / `<tbody>` / `<tfoot>` into `rows`.
+fn lower_row_group<'a>(
+    ctx: &mut Context<'a>,
+    group_node: &'a HTMLNode,
+    group: RowGroup,
+    rows: &mut Vec<Row<'a>>,
+) {
+    let row_nodes: Vec<&'a HTMLNode> = group_node
+        .children()
+        .into_iter()
+        .flatten()
+        .filter(|child| child.get_tag_name() == Some("tr"))
+        .collect();
+    for (index, row_node) in row_nodes.iter().enumerate() {
+        rows.push(lower_row(ctx, row_node, group, row_nodes.len() - index));
+    }
 }
 
-fn lower_cell<'a>(ctx: &mut Context<'a>, cell_node: &'a HTMLNode) -> Option<TableCell<'a>> {
-    let content = TextCompound::from_array(ctx, cell_node.children()?)?;
-    Some(match cell_node.get_tag_name() {
-        Some("th") => TableCell::Header(content),
-        _ => TableCell::Data(content),
+/// Lower a `<tr>`, `rows_left` being the number of rows from it to the
+/// end of its group, which a `rowspan="0"` cell spans.
+fn lower_row<'a>(
+    ctx: &mut Context<'a>,
+    row_node: &'a HTMLNode,
+    group: RowGroup,
+    rows_left: usize,
+) -> Row<'a> {
+    Row {
+        group,
+        cells: row_node
+            .children()
+            .into_iter()
+            .flatten()
+            .filter(|child| matches!(child.get_tag_name(), Some("td" | "th")))
+            .map(|cell_node| lower_cell(ctx, cell_node, rows_left))
+            .collect(),
+    }
+}
+
+/// Lower a `<td>` / `<th>`. An empty cell is kept, with empty content,
+/// so the cells after it stay in their columns.
+fn lower_cell<'a>(
+    ctx: &mut Context<'a>,
+    cell_node: &'a HTMLNode,
+    rows_left: usize,
+) -> TableCell<'a> {
+    let (attrs, children) = match cell_node {
+        HTMLNode::Element {
+            attrs, children, ..
+        } => (Some(attrs), children.as_slice()),
+        HTMLNode::Text(_) => (None, &[][..]),
+    };
+    let attr = |name: &str| attrs.and_then(|attrs| attrs.get(name));
+    // Limits from the HTML spec, which also has `rowspan="0"` reach the
+    // end of the row group.
+    let span = |name: &str, max: u32| {
+        attr(name)
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .map(|span| span.min(max))
+    };
+    let rowspan = match span("rowspan", 65534) {
+        Some(0) => rows_left as u32,
+        span => span.unwrap_or(1),
+    };
+    TableCell {
+        kind: match cell_node.get_tag_name() {
+            Some("th") => CellKind::Header(attr("scope").and_then(|scope| scope.parse().ok())),
+            _ => CellKind::Data,
+        },
+        content: TextCompound::from_array(ctx, children)
+            .unwrap_or_else(|| TextCompound::Array(Vec::new())),
+        colspan: span("colspan", 1000).unwrap_or(1).max(1),
+        rowspan: rowspan.max(1),
+    }
+}
+
+/// Lower an `<img>`: its source, unless it repeats the hero image, with
+/// alt text and intrinsic size.
+fn lower_image<'a>(ctx: &Context, attrs: &'a HashMap<String, String>) -> Option<Image<'a>> {
+    let alt = attrs
+        .get("alt")
+        .map(|alt| alt.trim())
+        .filter(|alt| !alt.is_empty());
+    let dimension = |name| attrs.get(name).and_then(|value| parse_dimension(value));
+    Some(Image {
+        src: extract_image_src(ctx, attrs)?,
+        alt: alt.map(Cow::Borrowed),
+        width: dimension("width"),
+        height: dimension("height"),
     })
 }
 
-/// If the last child of a `<figure>` is a `<figcaption>`, return its
-/// children. Otherwise return `None` — the caller falls back to the
-/// figure body.
-fn figcaption_children(siblings: &[HTMLNode]) -> Option<&[HTMLNode]> {
-    match siblings.last()? {
-        HTMLNode::Element { tag, children, .. } if tag == "figcaption" => Some(children),
-        _ => None,
+/// Lower a `<figure>` into its content and `<figcaption>`, wherever the
+/// caption sits among the children. A figure whose content didn't
+/// survive keeps its caption as a quote, so the explanation isn't lost.
+fn lower_figure<'a>(ctx: &mut Context<'a>, children: &'a [HTMLNode]) -> Option<TextCompound<'a>> {
+    let (captions, content): (Vec<&'a HTMLNode>, Vec<&'a HTMLNode>) = children
+        .iter()
+        .partition(|child| child.get_tag_name() == Some("figcaption"));
+    let caption = captions
+        .into_iter()
+        .flat_map(|caption| TextCompound::from_array(ctx, caption.children()?))
+        .next();
+    let mut content: Vec<TextCompound<'a>> = content
+        .into_iter()
+        .flat_map(|child| TextCompound::from_node(ctx, child))
+        .collect();
+    let content = match content.len() {
+        0 => None,
+        1 => content.pop(),
+        _ => Some(TextCompound::Array(content)),
+    };
+    match (content, caption) {
+        (Some(content), Some(caption)) => Some(TextCompound::figure(content, caption)),
+        (Some(content), None) => Some(content),
+        (None, caption) => caption.map(TextCompound::quote),
     }
 }
 
@@ -1,6 +1,7 @@
 use super::TableCell;
 
-/// A single table row — an ordered list of header-or-data cells.
+/// A single table row — an ordered list of header-or-data cells — and
+/// the section of the table it sits in.
 ///
 /// Exists so the table type hierarchy reads top-to-bottom: `Table ⇒
 /// Row ⇒ TableCell`. The previous representation was
@@ -8,5 +9,26 @@ use super::TableCell;
 /// reverse-engineer the shape.
 #[derive(Debug)]
 pub struct Row<'a> {
+    pub group: RowGroup,
     pub cells: Vec<TableCell<'a>>,
 }
+
+/// The `<thead>`, `<tbody>` or `<tfoot>` a row belongs to. Rows placed
+/// straight in the `<table>` are body rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RowGroup {
+    Head,
+    #[default]
+    Body,
+    Foot,
+}
+
+impl RowGroup {
+    pub fn html_tag(&self) -> &'static str {
+        match self {
+            Self::Head => "thead",
+            Self::Body => "tbody",
+            Self::Foot => "tfoot",
+        }
+    }
+}
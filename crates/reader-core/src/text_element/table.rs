@@ -1,8 +1,93 @@
-use super::Row;
+use super::{Row, TableCell, TextCompound};
+
+/// The widest grid [`Table::grid`] lays out, as browsers cap it: cells
+/// starting beyond it are dropped and spans reaching past it clipped.
+/// Each `colspan` is capped on its own, but a row of many wide cells
+/// could otherwise still ask for millions of slots.
+pub const MAX_COLUMNS: usize = 1000;
 
 /// A fully lowered `<table>`. Rows are ordered top-to-bottom as they
 /// appeared in the source document.
 #[derive(Debug)]
 pub struct Table<'a> {
+    pub caption: Option<Box<TextCompound<'a>>>,
     pub rows: Vec<Row<'a>>,
 }
+
+impl<'a> Table<'a> {
+    /// Whether the table only lays content out: a single column, with no
+    /// header cells and no caption. Such tables read better as plain
+    /// paragraphs.
+    pub fn is_layout(&self) -> bool {
+        self.caption.is_none()
+            && self
+                .rows
+                .iter()
+                .all(|row| row.cells.len() <= 1 && !row.cells.iter().any(TableCell::is_header))
+    }
+
+    /// The table as a grid of slots, spans resolved: each cell sits in
+    /// the slot of its top-left corner, and the slots it covers beyond
+    /// that, like those past the end of a short row, are `None`. Every
+    /// grid row is as wide as the widest, and none wider than
+    /// [`MAX_COLUMNS`].
+    pub fn grid(&self) -> Vec<Vec<Option<&TableCell<'a>>>> {
+        // Slots are `Some(None)` once covered by a span.
+        let mut grid: Vec<Vec<Option<Option<&TableCell<'a>>>>> = vec![Vec::new(); self.rows.len()];
+        for (y, row) in self.rows.iter().enumerate() {
+            let mut x = 0;
+            for cell in &row.cells {
+                while grid[y].get(x).is_some_and(Option::is_some) {
+                    x += 1;
+                }
+                if x >= MAX_COLUMNS {
+                    break;
+                }
+                let rows = (cell.rowspan as usize).clamp(1, self.rows.len() - y);
+                let columns = (cell.colspan as usize).clamp(1, MAX_COLUMNS - x);
+                for (dy, grid_row) in grid[y..y + rows].iter_mut().enumerate() {
+                    for dx in 0..columns {
+                        if grid_row.len() <= x + dx {
+                            grid_row.resize(x + dx + 1, None);
+                        }
+                        grid_row[x + dx] = Some((dy == 0 && dx == 0).then_some(cell));
+                    }
+                }
+                x += columns;
+            }
+        }
+        let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+        grid.into_iter()
+            .map(|mut row| {
+                row.resize(width, None);
+                row.into_iter().map(Option::flatten).collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{CellKind, RowGroup};
+    use super::*;
+
+    #[test]
+    fn grid_is_clipped_at_the_column_cap() {
+        let cell = |colspan| TableCell {
+            kind: CellKind::Data,
+            content: TextCompound::raw("x"),
+            colspan,
+            rowspan: 1,
+        };
+        let table = Table {
+            caption: None,
+            rows: vec![Row {
+                group: RowGroup::Body,
+                cells: vec![cell(1), cell(1000), cell(1000), cell(1)],
+            }],
+        };
+        let grid = table.grid();
+        assert_eq!(grid[0].len(), MAX_COLUMNS);
+        assert_eq!(grid[0].iter().flatten().count(), 2);
+    }
+}
@@ -1,26 +1,70 @@
+use std::str::FromStr;
+
 use super::TextCompound;
 
-/// One cell inside a `<table>`. `Header` maps to `<th>`, `Data` to `<td>` —
-/// there are no other kinds, which is exactly what `enum` is for. The
-/// previous representation was `(bool, TextCompound)`, which required the
-/// reader to remember which way the boolean pointed.
+/// One cell inside a `<table>`, with the rows and columns it spans
+/// (both at least 1).
 #[derive(Debug)]
-pub enum TableCell<'a> {
-    Header(TextCompound<'a>),
-    Data(TextCompound<'a>),
+pub struct TableCell<'a> {
+    pub kind: CellKind,
+    pub content: TextCompound<'a>,
+    pub colspan: u32,
+    pub rowspan: u32,
+}
+
+/// `Header` maps to `<th>`, with the cells it heads when the page says,
+/// `Data` to `<td>` — there are no other kinds, which is exactly what
+/// `enum` is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    Header(Option<Scope>),
+    Data,
+}
+
+/// The `scope` of a header cell: which cells it is the header for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Row,
+    Col,
+    RowGroup,
+    ColGroup,
 }
 
 impl<'a> TableCell<'a> {
-    pub fn content(&self) -> &TextCompound<'a> {
-        match self {
-            Self::Header(content) | Self::Data(content) => content,
-        }
+    pub fn is_header(&self) -> bool {
+        matches!(self.kind, CellKind::Header(_))
     }
 
     pub fn html_tag(&self) -> &'static str {
+        match self.kind {
+            CellKind::Header(_) => "th",
+            CellKind::Data => "td",
+        }
+    }
+}
+
+impl Scope {
+    /// The `scope` attribute value.
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Self::Header(_) => "th",
-            Self::Data(_) => "td",
+            Self::Row => "row",
+            Self::Col => "col",
+            Self::RowGroup => "rowgroup",
+            Self::ColGroup => "colgroup",
         }
     }
 }
+
+impl FromStr for Scope {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "row" => Self::Row,
+            "col" => Self::Col,
+            "rowgroup" => Self::RowGroup,
+            "colgroup" => Self::ColGroup,
+            _ => return Err("Invalid header scope"),
+        })
+    }
+}
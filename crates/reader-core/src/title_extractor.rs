@@ -1,8 +1,9 @@
 //! Regex-based scanner for the handful of metadata fields we care about.
 //!
 //! Rather than run a full html5ever parse just to grab `og:title`, we scan
-//! the `<head>` region of the raw HTML with a pair of meta-tag regexes and
-//! a `<title>` regex. This saves one full DOM construction per request.
+//! the `<head>` region of the raw HTML with a pair of meta-tag regexes, a
+//! `<title>` regex and a few `<base>` / `<link rel="canonical">` regexes.
+//! This saves one full DOM construction per request.
 
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -12,11 +13,17 @@ use regex::Regex;
 /// `title` / `image` come from Open Graph–style `<meta property=…>` tags;
 /// `html_title` is the text of the `<title>` element, used as a fallback
 /// when the page has no og:title and Readability doesn't guess one either.
+/// `base` and `canonical` are the raw `href`s of `<base>` and
+/// `<link rel="canonical">`, left for the pipeline to resolve.
+/// `language` is the `<html lang>` tag, kept only if it looks like one.
 #[derive(Default, Debug, Clone)]
 pub struct ArticleData {
     pub image: Option<String>,
     pub title: Option<String>,
     pub html_title: Option<String>,
+    pub base: Option<String>,
+    pub canonical: Option<String>,
+    pub language: Option<String>,
 }
 
 const TITLE_PROPERTIES: &[&str] = &["og:title", "title", "twiter:title", "discord:title"];
@@ -44,6 +51,21 @@ static META_CONTENT_PROP: Lazy<Regex> = Lazy::new(|| {
 });
 static TITLE_TAG: Lazy<Regex> =
     Lazy::new(|| Regex::new(r#"(?is)<title[^>]*>([^<]*)</title>"#).unwrap());
+static HTML_LANG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?is)<html\s+[^>]*?\blang\s*=\s*["']([^"']+)["']"#).unwrap());
+static LANGUAGE_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[A-Za-z]{1,8}(?:-[A-Za-z0-9]{1,8})*$").unwrap());
+static BASE_HREF: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?is)<base\s+[^>]*href\s*=\s*["']([^"']+)["']"#).unwrap());
+// `<link rel="canonical" href="…">` in either attribute order.
+static CANONICAL_REL_HREF: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<link\s+[^>]*rel\s*=\s*["']canonical["'][^>]*href\s*=\s*["']([^"']+)["']"#)
+        .unwrap()
+});
+static CANONICAL_HREF_REL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<link\s+[^>]*href\s*=\s*["']([^"']+)["'][^>]*rel\s*=\s*["']canonical["']"#)
+        .unwrap()
+});
 
 /// Scan raw HTML for Open Graph metadata and the `<title>` tag.
 pub fn try_extract_data(html: &str) -> ArticleData {
@@ -66,9 +88,20 @@ pub fn try_extract_data(html: &str) -> ArticleData {
         html_title,
         title,
         image,
+        base: first_capture(head, &BASE_HREF),
+        canonical: first_capture(head, &CANONICAL_REL_HREF)
+            .or_else(|| first_capture(head, &CANONICAL_HREF_REL)),
+        language: first_capture(head, &HTML_LANG).filter(|tag| LANGUAGE_TAG.is_match(tag)),
     }
 }
 
+fn first_capture(head: &str, regex: &Regex) -> Option<String> {
+    regex
+        .captures(head)
+        .map(|caps| decode(caps[1].trim()))
+        .filter(|href| !href.is_empty())
+}
+
 /// Slice the raw HTML down to the `<head>` region (plus the closing tag)
 /// so the regex scans have bounded input.
 fn head_region(html: &str) -> &str {
@@ -143,6 +176,33 @@ mod tests {
         assert!(data.image.is_none());
     }
 
+    #[test]
+    fn pulls_base_and_canonical_hrefs() {
+        let html = r#"
+            <head>
+              <base target="_blank" href="/blog/">
+              <link href="https://example.com/a?x=1&amp;y=2" rel="canonical">
+            </head>
+        "#;
+        let data = try_extract_data(html);
+        assert_eq!(data.base.as_deref(), Some("/blog/"));
+        assert_eq!(
+            data.canonical.as_deref(),
+            Some("https://example.com/a?x=1&y=2")
+        );
+        let data = try_extract_data(r#"<head><link rel="stylesheet" href="a.css"></head>"#);
+        assert!(data.canonical.is_none());
+    }
+
+    #[test]
+    fn pulls_the_document_language() {
+        let data = try_extract_data(r#"<html class="js" lang="pt-BR"><head></head>"#);
+        assert_eq!(data.language.as_deref(), Some("pt-BR"));
+        let data = try_extract_data(r#"<html lang="en&quot; onload"><head></head>"#);
+        assert!(data.language.is_none());
+        assert!(try_extract_data("<html><head></head>").language.is_none());
+    }
+
     #[test]
     fn ignores_non_matching_meta_properties() {
         let html = r#"<head><meta property="description" content="blurb"></head>"#;
@@ -54,7 +54,7 @@ pub fn extract_image_src<'a>(
         .filter(|(name, _)| is_url_attr(name))
         .filter(|(_, value)| looks_like_image(value))
         .find_map(|(_, value)| {
-            let absolute = absolutize_link(&ctx.url, value)?;
+            let absolute = absolutize_link(&ctx.base, value)?;
             let duplicates_hero = ctx
                 .meta
                 .image
@@ -104,6 +104,25 @@ pub fn absolutize_link<'a>(base: &Url, raw: &'a str) -> Option<Cow<'a, str>> {
     }
 }
 
+/// Resolve a document-level link (`<base href>`, `<link rel="canonical">`)
+/// against `base`, keeping it only if it points at an http(s) page.
+pub fn resolve_document_link(base: &Url, href: &str) -> Option<Url> {
+    base.join(href)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Whether `a` and `b` are on the same host, ignoring a leading `www.`.
+/// A page may only name a canonical URL on its own site; one elsewhere
+/// is more likely a syndication partner or a mistake than an alias.
+pub fn same_site(a: &Url, b: &Url) -> bool {
+    let host = |url: &Url| {
+        url.host_str()
+            .map(|host| host.strip_prefix("www.").unwrap_or(host).to_owned())
+    };
+    host(a).is_some() && host(a) == host(b)
+}
+
 /// Whether `url` ends with an extension that suggests it's not an HTML
 /// document. Used to decide whether to rewrite an outbound link through
 /// `/m/{short}`.
@@ -0,0 +1,174 @@
+//! Golden snapshot tests over the saved pages in `tests/golden`.
+//!
+//! Each subdirectory is a fixture bundle (see `reader_core::http::fixture`)
+//! — one recorded with `/record/{short}` can be copied in as is. The
+//! article is fetched from the bundle, run through the pipeline, and its
+//! `TextCompound` tree and View HTML compared with `tree.snap` and
+//! `view.html.snap` next to it. Every bundle's manifest must say what
+//! it was recorded from (`article`) and under which `license` it is
+//! redistributed; `tests/golden/README.md` lists the sources.
+//!
+//! After an intended extraction change, regenerate the snapshots with
+//! `UPDATE_GOLDEN=1 cargo test -p reader-core --test golden` and review
+//! the diff like any other change.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use reader_core::{
+    config::CONFIG_ENV,
+    http::{
+        fixture::{Manifest, MANIFEST},
+        FetchOptions, Fetcher, FixtureFetcher,
+    },
+    pipeline::{self, Snapshot},
+    RenderMode,
+};
+
+/// Lines of unchanged context printed around each difference.
+const CONTEXT: usize = 2;
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Fetch the bundle's article from the bundle and render it.
+fn render_bundle(dir: &Path) -> Snapshot {
+    let fixture = FixtureFetcher::from_dir(dir).expect("load bundle");
+    let article = fixture
+        .article()
+        .expect("bundle manifest names its article")
+        .to_owned();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("build runtime");
+    let page = runtime
+        .block_on(
+            fixture
+                .session()
+                .expect("open session")
+                .get_page(&article, &FetchOptions::default()),
+        )
+        .expect("fetch article from bundle")
+        .expect("unconditional fetch returns the page");
+    let fetcher: Arc<dyn Fetcher> = Arc::new(fixture);
+    pipeline::snapshot(fetcher, page.body, page.url, RenderMode::View).expect("render article")
+}
+
+/// What is missing from the bundle's provenance, if anything.
+fn missing_provenance(dir: &Path) -> Option<&'static str> {
+    let manifest = fs::read_to_string(dir.join(MANIFEST)).expect("read manifest");
+    let manifest: Manifest = toml::from_str(&manifest).expect("parse manifest");
+    if manifest.article.is_none() {
+        Some("article")
+    } else if manifest
+        .license
+        .as_deref()
+        .is_none_or(|license| license.trim().is_empty())
+    {
+        Some("license")
+    } else {
+        None
+    }
+}
+
+/// Compare `actual` with the snapshot at `path`, or overwrite it when
+/// updating. Returns a printable diff on mismatch.
+fn check(path: &Path, actual: &str, update: bool) -> Option<String> {
+    if update {
+        fs::write(path, actual).expect("write snapshot");
+        return None;
+    }
+    let expected = fs::read_to_string(path).unwrap_or_default();
+    (expected != actual).then(|| diff(&expected, actual))
+}
+
+/// Line diff of `expected` against `actual`, `-`/`+` prefixed, with a
+/// little context around each change.
+fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    // Longest common subsequence lengths of every pair of suffixes.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    let changed: Vec<usize> = (0..lines.len()).filter(|&k| lines[k].0 != ' ').collect();
+    let mut out = String::new();
+    let mut printed_to = 0;
+    for &k in &changed {
+        let start = k.saturating_sub(CONTEXT).max(printed_to);
+        if start > printed_to {
+            out.push_str("   ...\n");
+        }
+        let end = (k + CONTEXT + 1).min(lines.len());
+        for (sign, line) in &lines[start..end] {
+            let _ = writeln!(out, " {} {}", sign, line);
+        }
+        printed_to = printed_to.max(end);
+    }
+    out
+}
+
+#[test]
+fn corpus_matches_snapshots() {
+    std::env::set_var(CONFIG_ENV, corpus_dir().join("config.toml"));
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut bundles: Vec<PathBuf> = fs::read_dir(corpus_dir())
+        .expect("read corpus")
+        .map(|entry| entry.expect("corpus entry").path())
+        .filter(|path| path.is_dir())
+        .collect();
+    bundles.sort();
+    assert!(!bundles.is_empty(), "no pages in the golden corpus");
+
+    let mut failures = String::new();
+    for dir in &bundles {
+        if let Some(field) = missing_provenance(dir) {
+            let name = dir.file_name().unwrap_or_default().to_string_lossy();
+            let _ = writeln!(failures, "\n{}: manifest has no `{}`", name, field);
+            continue;
+        }
+        let snapshot = render_bundle(dir);
+        for (file, actual) in [
+            ("tree.snap", &snapshot.tree),
+            ("view.html.snap", &snapshot.body),
+        ] {
+            if let Some(diff) = check(&dir.join(file), actual, update) {
+                let name = dir.file_name().unwrap_or_default().to_string_lossy();
+                let _ = writeln!(failures, "\n{}/{} changed:\n{}", name, file, diff);
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "golden snapshots differ (rerun with UPDATE_GOLDEN=1 to accept):\n{}",
+        failures
+    );
+}
@@ -1,6 +1,7 @@
+use actix_web::http::StatusCode;
 use image_actor::ImageActorError;
 use page_actor::PageActorError;
-use reader_core::CacheError;
+use reader_core::{CacheError, EpubError, HttpError, PipelineError};
 
 /// Top-level error type for the HTTP binding. Aggregates the narrow
 /// errors returned by each downstream layer via `#[from]`; each handler
@@ -10,12 +11,21 @@ pub enum ServerError {
     #[error(transparent)]
     Cache(#[from] CacheError),
 
+    #[error(transparent)]
+    Epub(#[from] EpubError),
+
     #[error(transparent)]
     PageActor(#[from] PageActorError),
 
     #[error(transparent)]
     ImageActor(#[from] ImageActorError),
 
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+
+    #[error(transparent)]
+    Http(#[from] HttpError),
+
     #[error("Invalid base64 payload: {0}")]
     Base64(#[from] base64::DecodeError),
 
@@ -28,3 +38,61 @@ pub enum ServerError {
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 }
+
+impl ServerError {
+    /// The URL `robots.txt` kept us from fetching, when that is why the
+    /// request failed.
+    pub fn disallowed_by_robots(&self) -> Option<&str> {
+        match self.http() {
+            Some(HttpError::DisallowedByRobots(url)) => Some(url),
+            _ => None,
+        }
+    }
+
+    /// The 4xx status for a failure caused by the request itself: a bad
+    /// URL or payload, a blocked address, or a page with no article to
+    /// extract. `None` means the fault is ours or the origin's.
+    pub fn client_error_status(&self) -> Option<StatusCode> {
+        match self {
+            ServerError::UnknownShortId => return Some(StatusCode::NOT_FOUND),
+            ServerError::Base64(_) | ServerError::Utf8(_) => return Some(StatusCode::BAD_REQUEST),
+            _ => {}
+        }
+        match self.pipeline() {
+            Some(PipelineError::InvalidUrl(_)) => return Some(StatusCode::BAD_REQUEST),
+            Some(
+                PipelineError::EmptyArticle
+                | PipelineError::Readability(_)
+                | PipelineError::Html(_),
+            ) => return Some(StatusCode::UNPROCESSABLE_ENTITY),
+            _ => {}
+        }
+        match self.http()? {
+            HttpError::InvalidUrl(_) => Some(StatusCode::BAD_REQUEST),
+            HttpError::BlockedAddress { .. } | HttpError::DisallowedByRobots(_) => {
+                Some(StatusCode::FORBIDDEN)
+            }
+            _ => None,
+        }
+    }
+
+    fn pipeline(&self) -> Option<&PipelineError> {
+        match self {
+            ServerError::PageActor(PageActorError::Pipeline(e)) => Some(e),
+            ServerError::Pipeline(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The fetch error behind the failure, raised directly or through
+    /// the pipeline.
+    fn http(&self) -> Option<&HttpError> {
+        match self {
+            ServerError::Http(e) => Some(e),
+            _ => match self.pipeline()? {
+                PipelineError::Http(e) => Some(e),
+                _ => None,
+            },
+        }
+    }
+}
@@ -1,9 +1,16 @@
 mod error;
 
-use actix_web::{get, web, App, HttpResponse, HttpServer};
-use reader_core::cache::{self, get_shortened_from_url, get_url_for_shortened};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{get, http::header, post, web, App, HttpRequest, HttpResponse, HttpServer};
+use reader_core::cache::{self, get_shortened_from_url, get_url_for_shortened, CacheKind};
 use reader_core::config::CONFIG;
-use reader_core::RenderMode;
+use reader_core::http::{
+    identity, FetchOptions, Fetcher, NetworkFetcher, PoliteFetcher, Validators,
+};
+use reader_core::{epub, pipeline, CacheError, PipelineError, RenderMode};
 use tokio::fs;
 
 use crate::error::ServerError;
@@ -25,46 +32,359 @@ async fn index_r(base64url: web::Path<String>) -> HttpResponse {
 }
 
 /// Resolve a short id to a URL, serve from the disk cache if enabled,
-/// else ask the page actor to render it and store the result.
-async fn serve_short(short: String, mode: RenderMode) -> HttpResponse {
-    let output: Result<String> = async {
-        let url = get_url_for_shortened(&short)?.ok_or(ServerError::UnknownShortId)?;
-        eprintln!("serving {}", url);
-        if let Some(cached) = cache::try_cached(&url).await? {
-            return Ok(cached);
+/// else ask the page actor to render it and store the result. Snapshot
+/// ids render their submitted HTML and are cached under the id itself;
+/// since a snapshot never changes, its render never expires. A URL render
+/// past `cache_ttl` is revalidated against the origin and re-rendered
+/// only if the page changed. A forwarded `accept_language` is sent to the
+/// origin and the render cached separately for it. A render is only
+/// ever cached under the URL that was fetched; a canonical URL the page
+/// declares is recorded as an alias for later lookups of that URL.
+async fn render_short(
+    short: &str,
+    mode: RenderMode,
+    accept_language: Option<&str>,
+) -> Result<String> {
+    let url = get_url_for_shortened(short)?.ok_or(ServerError::UnknownShortId)?;
+    eprintln!("serving {}", url);
+    let snapshot = cache::get_snapshot(short)?;
+    let key = match &snapshot {
+        Some(_) => short.to_owned(),
+        None => render_key(&url, accept_language),
+    };
+    let key = key.as_str();
+    let cached = cache::try_cached(key, mode).await?;
+    if let Some(cached) = cached
+        .as_ref()
+        .filter(|c| snapshot.is_some() || c.is_fresh())
+    {
+        return Ok(cached.body.clone());
+    }
+    if let Some(html) = snapshot {
+        let rendered = page_actor::render_html_page(&url, html, short, mode).await?;
+        cache::store(key, mode, &rendered, &Validators::default()).await;
+        return Ok(rendered);
+    }
+    let fetch = FetchOptions {
+        revalidate: cached
+            .as_ref()
+            .map(|c| c.validators.clone())
+            .filter(|v| !v.is_empty()),
+        accept_language: accept_language.map(str::to_owned),
+    };
+    match (
+        page_actor::render_page(&url, short, mode, fetch).await?,
+        cached,
+    ) {
+        (Some(page), _) => {
+            if let Some(canonical) = page.canonical_url.as_deref().filter(|c| *c != url) {
+                cache::record_canonical(&url, canonical)?;
+            }
+            cache::store(key, mode, &page.body, &page.validators).await;
+            Ok(page.body)
         }
-        let rendered = page_actor::render_page(&url, &short, mode).await?;
-        cache::store(&url, &rendered).await;
-        Ok(rendered)
+        (None, Some(cached)) => {
+            cache::touch(key, mode)?;
+            Ok(cached.body)
+        }
+        // A conditional fetch is only made for a cached render, so the
+        // fetcher broke its contract; fail the request, not the server.
+        (None, None) => Err(PipelineError::UnexpectedNotModified.into()),
+    }
+}
+
+/// Cache key of a fetched render: the URL, plus the forwarded
+/// `Accept-Language` when there is one.
+fn render_key(url: &str, accept_language: Option<&str>) -> String {
+    match accept_language {
+        Some(language) => format!("{}\nAccept-Language: {}", url, language),
+        None => url.to_owned(),
+    }
+}
+
+/// The reader's `Accept-Language`, when the config says to forward it,
+/// normalised since it keys the cache. The extension's `/r/` link
+/// redirects to `/m/`, so the browser's header arrives on every render
+/// endpoint.
+fn reader_language(req: &HttpRequest) -> Option<String> {
+    if !CONFIG.identity.forward_accept_language {
+        return None;
+    }
+    req.headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(identity::reader_accept_language)
+}
+
+async fn serve_short(req: &HttpRequest, short: String, mode: RenderMode) -> HttpResponse {
+    match render_short(&short, mode, reader_language(req).as_deref()).await {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(mode.content_type())
+            .body(body),
+        Err(e) => error_response(e),
+    }
+}
+
+/// The response for a failed render: a page explaining the refusal when
+/// `robots.txt` disallows the article, the matching 4xx when the request
+/// itself is at fault (an unknown id, a bad URL, nothing to extract),
+/// 500 for anything else.
+fn error_response(e: ServerError) -> HttpResponse {
+    if let Some(url) = e.disallowed_by_robots() {
+        let url = html_escape::encode_double_quoted_attribute(url);
+        return HttpResponse::Forbidden()
+            .content_type("text/html; charset=utf-8")
+            .body(format!(
+                "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Blocked by robots.txt</title></head>\n\
+                 <body><h1>Blocked by robots.txt</h1>\n\
+                 <p>This server respects the <code>robots.txt</code> of the sites it fetches, \
+                 and that of this site disallows it from reading <a href=\"{0}\">{0}</a>.</p>\n\
+                 <p>You can still read the article on the original site.</p></body></html>\n",
+                url
+            ));
+    }
+    match e.client_error_status() {
+        Some(status) => HttpResponse::build(status).body(e.to_string()),
+        None => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Render every short id as an EPUB chapter, in order, and bundle them
+/// into one book. The download gets a fixed name, since the ids come
+/// straight from the request.
+async fn serve_epub(req: &HttpRequest, shorts: &[&str]) -> HttpResponse {
+    let language = reader_language(req);
+    let output: Result<Vec<u8>> = async {
+        let mut chapters = Vec::with_capacity(shorts.len());
+        for short in shorts {
+            chapters.push(render_short(short, RenderMode::Epub, language.as_deref()).await?);
+        }
+        Ok(epub::package(&chapters)?)
     }
     .await;
     match output {
-        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
-        Err(ServerError::UnknownShortId) => HttpResponse::NotFound().body("unknown short id"),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Ok(book) => HttpResponse::Ok()
+            .content_type("application/epub+zip")
+            .insert_header((
+                "content-disposition",
+                if shorts.len() == 1 {
+                    "attachment; filename=\"article.epub\""
+                } else {
+                    "attachment; filename=\"articles.epub\""
+                },
+            ))
+            .body(book),
+        Err(e) => error_response(e),
+    }
+}
+
+/// DOM snapshot posted by the extension: the page as the user's browser
+/// rendered it, logged-in state and client-side scripts included.
+#[derive(serde::Deserialize)]
+struct RenderRequest {
+    url: String,
+    html: String,
+}
+
+#[derive(serde::Serialize)]
+struct RenderResponse {
+    short: String,
+    path: String,
+}
+
+/// Render a submitted DOM snapshot without fetching it. The render runs
+/// up-front so extraction failures are reported to the extension, and the
+/// returned short id serves the result like any other article.
+#[post("/api/render")]
+async fn api_render(req: HttpRequest, request: web::Json<RenderRequest>) -> HttpResponse {
+    let RenderRequest { url, html } = request.into_inner();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return HttpResponse::BadRequest().body("url must be http(s)");
+    }
+    let output: Result<String> = async {
+        let short = cache::store_snapshot(&url, &html)?;
+        render_short(&short, RenderMode::View, reader_language(&req).as_deref()).await?;
+        Ok(short)
+    }
+    .await;
+    match output {
+        Ok(short) => HttpResponse::Ok().json(RenderResponse {
+            path: format!("/m/{}", short),
+            short,
+        }),
+        Err(e) => error_response(e),
     }
 }
 
 #[get("/m/{short}")]
-async fn index_m(short: web::Path<String>) -> HttpResponse {
-    serve_short(short.into_inner(), RenderMode::View).await
+async fn index_m(req: HttpRequest, short: web::Path<String>) -> HttpResponse {
+    serve_short(&req, short.into_inner(), RenderMode::View).await
 }
 
 #[get("/i/{short}")]
 async fn index_i(short: web::Path<String>) -> HttpResponse {
-    let path = format!("{}/images/{}.avif", CONFIG.cache_folder, short.into_inner());
-    match fs::read(path).await {
-        Ok(bytes) => HttpResponse::Ok().content_type("image/avif").body(bytes),
+    let short = short.into_inner();
+    let path = format!("{}/images/{}.avif", CONFIG.cache_folder, short);
+    match fs::read(&path).await {
+        Ok(bytes) => {
+            if let Err(e) = cache::record_access(&path) {
+                eprintln!("image cache access {}: {}", path, e);
+            }
+            HttpResponse::Ok().content_type("image/avif").body(bytes)
+        }
+        // Not re-encoded yet, or evicted since: send the reader to the
+        // original image.
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            HttpResponse::NotFound().body("image not yet cached")
+            match cache::get_image_source(&short) {
+                Ok(Some(url)) => HttpResponse::Found()
+                    .insert_header((header::LOCATION, url))
+                    .finish(),
+                Ok(None) => HttpResponse::NotFound().body("image not yet cached"),
+                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+            }
         }
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
 #[get("/d/{short}")]
-async fn download(short: web::Path<String>) -> HttpResponse {
-    serve_short(short.into_inner(), RenderMode::Download).await
+async fn download(req: HttpRequest, short: web::Path<String>) -> HttpResponse {
+    serve_short(&req, short.into_inner(), RenderMode::Download).await
+}
+
+#[get("/md/{short}")]
+async fn markdown(req: HttpRequest, short: web::Path<String>) -> HttpResponse {
+    serve_short(&req, short.into_inner(), RenderMode::Markdown).await
+}
+
+#[get("/epub/{short}")]
+async fn epub_single(req: HttpRequest, short: web::Path<String>) -> HttpResponse {
+    serve_epub(&req, &[short.as_str()]).await
+}
+
+#[derive(serde::Deserialize)]
+struct EpubQuery {
+    ids: String,
+}
+
+/// Most articles one `/epub?ids=…` request may bundle; each is a full
+/// render.
+const MAX_EPUB_CHAPTERS: usize = 50;
+
+/// `/epub?ids=a,b,c` — several articles bundled as one book.
+#[get("/epub")]
+async fn epub_bundle(req: HttpRequest, query: web::Query<EpubQuery>) -> HttpResponse {
+    let shorts: Vec<&str> = query
+        .ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect();
+    if shorts.is_empty() {
+        return HttpResponse::BadRequest().body("ids must name at least one article");
+    }
+    if shorts.len() > MAX_EPUB_CHAPTERS {
+        return HttpResponse::BadRequest().body(format!(
+            "at most {} articles fit in one book",
+            MAX_EPUB_CHAPTERS
+        ));
+    }
+    serve_epub(&req, &shorts).await
+}
+
+/// Render an article straight from the origin, bypassing the cache and
+/// the page actor but fetching through the same fetcher it uses, and save
+/// every response the render fetched, plus its images' originals, as a
+/// fixture bundle named after the short id.
+#[get("/record/{short}")]
+async fn record(short: web::Path<String>, fetcher: web::Data<dyn Fetcher>) -> HttpResponse {
+    if !CONFIG.enable_fixtures {
+        return HttpResponse::NotFound().body("fixture recording is disabled");
+    }
+    let short = short.into_inner();
+    let output: Result<String> = async {
+        let url = get_url_for_shortened(&short)?.ok_or(ServerError::UnknownShortId)?;
+        let bundle = Path::new(&CONFIG.fixture_folder).join(&short);
+        let saved = pipeline::record(fetcher.into_inner(), &url, &short, &bundle).await?;
+        Ok(format!(
+            "Recorded {} responses for {} to {}",
+            saved,
+            url,
+            bundle.display()
+        ))
+    }
+    .await;
+    match output {
+        Ok(summary) => HttpResponse::Ok().body(summary),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Render a recorded fixture bundle without touching the network.
+#[get("/replay/{name}")]
+async fn replay(name: web::Path<String>) -> HttpResponse {
+    if !CONFIG.enable_fixtures {
+        return HttpResponse::NotFound().body("fixture replay is disabled");
+    }
+    let name = name.into_inner();
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return HttpResponse::BadRequest().body("invalid bundle name");
+    }
+    let bundle = Path::new(&CONFIG.fixture_folder).join(&name);
+    match pipeline::replay(&bundle, &name, RenderMode::View).await {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(RenderMode::View.content_type())
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Track cache files written before entries were recorded, then trim
+/// both caches back under their budgets every `cache_eviction_interval`.
+fn spawn_cache_eviction() {
+    actix_web::rt::spawn(async {
+        let images = Path::new(&CONFIG.cache_folder).join("images");
+        let pages = Path::new(&CONFIG.cache_folder)
+            .join("pages")
+            .join(cache::generation());
+        let adopted = web::block(move || {
+            Ok::<_, CacheError>(
+                cache::adopt_untracked(CacheKind::Image, &images)?
+                    + cache::adopt_untracked(CacheKind::Render, &pages)?,
+            )
+        })
+        .await;
+        match adopted {
+            Ok(Ok(0)) => {}
+            Ok(Ok(n)) => println!("Tracking {} existing cache files", n),
+            Ok(Err(e)) => eprintln!("cache adoption failed: {}", e),
+            Err(e) => eprintln!("cache adoption task failed: {}", e),
+        }
+        let period = Duration::from_secs(CONFIG.cache_eviction_interval.max(1));
+        let mut interval = actix_web::rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            let _ = web::block(evict_caches).await;
+        }
+    });
+}
+
+fn evict_caches() {
+    for kind in [CacheKind::Render, CacheKind::Image, CacheKind::Snapshot] {
+        match cache::evict(kind) {
+            Ok(evicted) if evicted.entries > 0 => println!(
+                "Evicted {} {} cache entries ({} bytes)",
+                evicted.entries,
+                kind.as_str(),
+                evicted.bytes
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!("{} cache eviction failed: {}", kind.as_str(), e),
+        }
+    }
 }
 
 #[actix_web::main]
@@ -75,21 +395,44 @@ async fn main() -> std::io::Result<()> {
     println!("Clean Reader listening on {}", base);
     println!("Try it: {}/r/{}  ({})", base, example_encoded, example_url);
 
+    if cache::is_enabled() {
+        match cache::purge_stale() {
+            Ok(0) => {}
+            Ok(n) => println!("Purged {} stale cache entries", n),
+            Err(e) => eprintln!("cache purge failed: {}", e),
+        }
+    }
+    spawn_cache_eviction();
+
     if let Err(e) = image_actor::boot().await {
         eprintln!("failed to start image actor: {}", e);
         return Err(std::io::Error::other(e.to_string()));
     }
-    if let Err(e) = page_actor::boot().await {
+    let fetcher: Arc<dyn Fetcher> = if CONFIG.robots.enabled {
+        Arc::new(PoliteFetcher::new(Arc::new(NetworkFetcher)))
+    } else {
+        Arc::new(NetworkFetcher)
+    };
+    if let Err(e) = page_actor::boot(Arc::clone(&fetcher)).await {
         eprintln!("failed to start page actor: {}", e);
         return Err(std::io::Error::other(e.to_string()));
     }
 
-    HttpServer::new(|| {
+    let fetcher = web::Data::from(fetcher);
+    HttpServer::new(move || {
         App::new()
+            .app_data(web::JsonConfig::default().limit(CONFIG.max_size as usize))
+            .app_data(fetcher.clone())
             .service(index_r)
             .service(index_m)
             .service(index_i)
             .service(download)
+            .service(markdown)
+            .service(epub_single)
+            .service(epub_bundle)
+            .service(api_render)
+            .service(record)
+            .service(replay)
     })
     .bind(&CONFIG.address)?
     .run()
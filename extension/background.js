@@ -44,3 +44,25 @@ browser.contextMenus.onClicked.addListener((info, _tab) => {
         browser.tabs.create({ url: target });
     });
 });
+
+// "http://host:8080/r/" -> "http://host:8080"
+function serverOrigin(x) {
+    return genURL(x).replace(/\/r\/$/, "");
+}
+
+// DOM snapshots from cr.js: POST them to the server and send the tab to
+// the cleaned result.
+browser.runtime.onMessage.addListener((message, sender) => {
+    if (message.type !== "render-snapshot" || !sender.tab) return;
+    browser.storage.local.get(["server_url"], (result) => {
+        const origin = serverOrigin(result.server_url);
+        fetch(origin + "/api/render", {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ url: message.url, html: message.html })
+        })
+            .then((resp) => resp.ok ? resp.json() : resp.text().then((t) => Promise.reject(new Error(t))))
+            .then(({ path }) => browser.tabs.update(sender.tab.id, { url: origin + path }))
+            .catch((err) => console.error("Clean Reader snapshot failed:", err));
+    });
+});
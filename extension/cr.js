@@ -55,6 +55,18 @@
         window.location.href = genURL(serverUrl) + encodeTarget(targetUrl);
     }
 
+    // Send the page as the browser rendered it (logged-in content,
+    // client-side rendering) instead of letting the server re-fetch it.
+    // The background worker does the POST: content scripts are bound by
+    // the page's CORS policy.
+    function sendSnapshot() {
+        browser.runtime.sendMessage({
+            type: "render-snapshot",
+            url: window.location.href,
+            html: document.documentElement.outerHTML
+        });
+    }
+
     function cleanReadCurrent() {
         const hovered = document.querySelectorAll("a:hover");
         if (hovered.length === 0 || !isUrl(hovered[0])) {
@@ -88,7 +100,7 @@
         const btn = document.createElement("div");
         btn.id = "__clean_reader_btn";
         btn.textContent = "CR";
-        btn.title = "Open in Clean Reader";
+        btn.title = "Open in Clean Reader (Shift+click: send the page as displayed)";
         btn.style.cssText = [
             "position:fixed",
             "top:12px",
@@ -111,7 +123,11 @@
         btn.addEventListener("click", (e) => {
             e.preventDefault();
             e.stopPropagation();
-            openCleanRead(window.location.href);
+            if (e.shiftKey) {
+                sendSnapshot();
+            } else {
+                openCleanRead(window.location.href);
+            }
         });
         document.body.appendChild(btn);
     }